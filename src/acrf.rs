@@ -1,4 +1,8 @@
+pub mod annotate;
 pub mod builder;
+pub mod check;
 mod combiner;
-mod linker;
-mod toc;
+pub mod label;
+pub mod linker;
+pub mod toc;
+pub mod view;
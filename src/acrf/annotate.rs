@@ -0,0 +1,549 @@
+pub mod extract;
+pub mod spec;
+mod text;
+
+use crate::ecrf::ECRF;
+use lopdf::{dictionary, text_string, Dictionary, Document, Object, ObjectId};
+use rust_xlsxwriter::{Format, Workbook};
+use spec::SpecRow;
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    path::Path,
+};
+use text::{normalize, page_lines, words, TextLine};
+
+/// fill colours of domains in order of their appearance on page: blue, yellow, green and orange
+const DOMAIN_COLOURS: [[f32; 3]; 4] = [
+    [0.75, 1.0, 1.0],
+    [1.0, 1.0, 0.59],
+    [0.59, 1.0, 0.59],
+    [1.0, 0.75, 0.61],
+];
+/// fill colour of fields not submitted
+const NOT_SUBMITTED_COLOUR: [f32; 3] = [0.85, 0.85, 0.85];
+const FONT_SIZE: f32 = 9.0;
+const MARGIN: f32 = 20.0;
+const GAP: f32 = 4.0;
+
+pub struct AnnotateParam<'a, P: AsRef<Path>> {
+    /// blank ecrf to annotate
+    pub source: P,
+    pub destination: P,
+    pub spec: &'a [SpecRow],
+    pub ecrf: &'a dyn ECRF,
+}
+
+/// field of spec which could not be annotated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unplaced {
+    pub form: String,
+    pub label: String,
+    pub domain: String,
+    pub variable: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AnnotateReport {
+    /// count of annotations placed for fields, domain headers are not counted
+    pub placed: usize,
+    pub unplaced: Vec<Unplaced>,
+}
+
+impl AnnotateReport {
+    pub fn write_xlsx<P: AsRef<Path>>(&self, p: P) -> anyhow::Result<()> {
+        let mut workbook = Workbook::new();
+        let header = Format::new().set_bold();
+        let sheet = workbook.add_worksheet();
+        sheet.set_name("Unplaced")?;
+        for (col, title) in ["Form", "Field Label", "Domain", "Variable", "Reason"]
+            .iter()
+            .enumerate()
+        {
+            sheet.write_string_with_format(0, col as u16, *title, &header)?;
+        }
+        for (index, field) in self.unplaced.iter().enumerate() {
+            let row = index as u32 + 1;
+            sheet.write_string(row, 0, &field.form)?;
+            sheet.write_string(row, 1, &field.label)?;
+            sheet.write_string(row, 2, &field.domain)?;
+            sheet.write_string(row, 3, field.variable.as_deref().unwrap_or_default())?;
+            sheet.write_string(row, 4, &field.reason)?;
+        }
+        sheet.set_column_width(1, 40)?;
+        sheet.set_column_width(4, 40)?;
+        workbook.save(p)?;
+        Ok(())
+    }
+}
+
+/// field found on page, waiting to be annotated
+struct Placement<'a> {
+    row: &'a SpecRow,
+    line: TextLine,
+}
+
+/// how label of field is matched with a line, tried in the listed order for all fields of form,
+/// so that a short label does not take the line of a longer label containing it
+#[derive(Debug, Clone, Copy)]
+enum LabelMatch {
+    Exact,
+    /// label is a run of whole words of line
+    Words,
+    Substring,
+}
+
+impl LabelMatch {
+    fn matches(&self, label: &str, line: &str) -> bool {
+        match self {
+            LabelMatch::Exact => normalize(line).eq(&normalize(label)),
+            LabelMatch::Words => {
+                let label = words(label);
+                !label.is_empty() && words(line).windows(label.len()).any(|w| w.eq(&label))
+            }
+            LabelMatch::Substring => normalize(line).contains(&normalize(label)),
+        }
+    }
+}
+
+/// annotate fields of spec onto their form pages, fields are found by label on pages of form,
+/// each line takes at most one field and fields matching several lines are left unplaced
+pub fn annotate<P: AsRef<Path>>(param: AnnotateParam<P>) -> anyhow::Result<AnnotateReport> {
+    let AnnotateParam {
+        source,
+        destination,
+        spec,
+        ecrf,
+    } = param;
+    let mut doc = Document::load(source.as_ref())?;
+    let pages = doc.get_pages();
+    let mut report = AnnotateReport::default();
+
+    let mut forms: Vec<(&str, Vec<&SpecRow>)> = vec![];
+    for row in spec {
+        match forms.iter_mut().find(|(form, _)| form.eq(&row.form)) {
+            Some((_, rows)) => rows.push(row),
+            None => forms.push((&row.form, vec![row])),
+        }
+    }
+
+    let mut placements: HashMap<u32, Vec<Placement>> = HashMap::new();
+    let mut lines: HashMap<u32, Vec<TextLine>> = HashMap::new();
+    // page and index of lines which already took a field
+    let mut taken: HashSet<(u32, usize)> = HashSet::new();
+    for (form, rows) in forms {
+        let Some(range) = ecrf.form_pages(form, pages.len()) else {
+            report
+                .unplaced
+                .extend(rows.iter().map(|r| unplaced(r, "form not found in ecrf")));
+            continue;
+        };
+        let mut form_lines = vec![];
+        for page in range.clone().map(|p| p as u32) {
+            let Some(page_id) = pages.get(&page) else {
+                break;
+            };
+            if let Entry::Vacant(entry) = lines.entry(page) {
+                entry.insert(page_lines(&doc, *page_id)?);
+            }
+            form_lines.extend((0..lines[&page].len()).map(|index| (page, index)));
+        }
+        let mut pending = rows;
+        for kind in [LabelMatch::Exact, LabelMatch::Words, LabelMatch::Substring] {
+            let mut rest = vec![];
+            for row in pending {
+                let found = form_lines
+                    .iter()
+                    .filter(|line| !taken.contains(*line))
+                    .filter(|(page, index)| kind.matches(&row.label, &lines[page][*index].text))
+                    .collect::<Vec<_>>();
+                match found[..] {
+                    [] => rest.push(row),
+                    [&(page, index)] => {
+                        taken.insert((page, index));
+                        placements.entry(page).or_default().push(Placement {
+                            row,
+                            line: lines[&page][index].clone(),
+                        });
+                    }
+                    _ => report.unplaced.push(unplaced(
+                        row,
+                        &format!(
+                            "label matches {} lines on pages {}-{}",
+                            found.len(),
+                            range.start(),
+                            range.end()
+                        ),
+                    )),
+                }
+            }
+            pending = rest;
+        }
+        report.unplaced.extend(pending.into_iter().map(|row| {
+            unplaced(
+                row,
+                &format!("label not found on pages {}-{}", range.start(), range.end()),
+            )
+        }));
+    }
+
+    for (page, placements) in placements {
+        report.placed += placements.len();
+        annotate_page(&mut doc, pages[&page], placements)?;
+    }
+    doc.save(destination.as_ref())?;
+    Ok(report)
+}
+
+fn unplaced(row: &SpecRow, reason: &str) -> Unplaced {
+    Unplaced {
+        form: row.form.clone(),
+        label: row.label.clone(),
+        domain: row.domain.clone(),
+        variable: row.variable.clone(),
+        reason: reason.into(),
+    }
+}
+
+/// domain headers go to the top left of page, fields are put at their label line
+/// from the right margin leftwards
+fn annotate_page(
+    doc: &mut Document,
+    page_id: ObjectId,
+    placements: Vec<Placement>,
+) -> anyhow::Result<()> {
+    let [left, _, right, top] = media_box(doc, page_id);
+    let mut domains: Vec<&str> = vec![];
+    for placement in placements.iter().filter(|p| !p.row.not_submitted) {
+        if !domains.contains(&placement.row.domain.as_str()) {
+            domains.push(&placement.row.domain);
+        }
+    }
+    let colour = |domain: &str| {
+        domains
+            .iter()
+            .position(|d| d.eq(&domain))
+            .map(|i| DOMAIN_COLOURS[i % DOMAIN_COLOURS.len()])
+            .unwrap_or(NOT_SUBMITTED_COLOUR)
+    };
+
+    let mut annotations = vec![];
+    let mut x = left + MARGIN;
+    for domain in domains.iter() {
+        let width = text_width(domain);
+        annotations.push(free_text(
+            [
+                x,
+                top - MARGIN - FONT_SIZE - 2.0 * GAP,
+                x + width,
+                top - MARGIN,
+            ],
+            domain,
+            colour(domain),
+        ));
+        x += width + GAP;
+    }
+
+    // fields on the same line share one row of annotations
+    let mut rows: Vec<(f32, f32)> = vec![];
+    for placement in placements.iter() {
+        let text = placement.row.annotation();
+        let width = text_width(&text);
+        let line = &placement.line;
+        let end = match rows.iter_mut().find(|(y, _)| (y - line.y).abs() < 1.0) {
+            Some((_, end)) => end,
+            None => {
+                rows.push((line.y, right - MARGIN));
+                &mut rows.last_mut().unwrap().1
+            }
+        };
+        let start = (*end - width).max(line.right + GAP);
+        let colour = if placement.row.not_submitted {
+            NOT_SUBMITTED_COLOUR
+        } else {
+            colour(&placement.row.domain)
+        };
+        annotations.push(free_text(
+            [start, line.y - GAP, start + width, line.y + FONT_SIZE + GAP],
+            &text,
+            colour,
+        ));
+        *end = start - GAP;
+    }
+
+    let ids = annotations
+        .into_iter()
+        .map(|annotation| Object::Reference(doc.add_object(annotation)))
+        .collect::<Vec<_>>();
+    append_annotations(doc, page_id, ids)
+}
+
+/// width of box fitting text, wide characters take a full em and others half of it
+fn text_width(text: &str) -> f32 {
+    text.chars()
+        .map(|c| if c.is_ascii() { 0.6 } else { 1.0 })
+        .sum::<f32>()
+        * FONT_SIZE
+        + 2.0 * GAP
+}
+
+fn free_text(rect: [f32; 4], text: &str, colour: [f32; 3]) -> Dictionary {
+    dictionary! {
+        "Type" => "Annot",
+        "Subtype" => "FreeText",
+        "Rect" => rect.iter().map(|v| Object::Real(*v)).collect::<Vec<_>>(),
+        "Contents" => text_string(text),
+        "DA" => Object::string_literal(format!("/Helv {} Tf 0 g", FONT_SIZE)),
+        "C" => colour.iter().map(|v| Object::Real(*v)).collect::<Vec<_>>(),
+        "F" => 4,
+    }
+}
+
+/// media box of page or of its ancestors, A4 if not found
+fn media_box(doc: &Document, page_id: ObjectId) -> [f32; 4] {
+    let mut node = doc.get_dictionary(page_id).ok();
+    while let Some(dict) = node {
+        if let Ok(Ok(values)) = dict
+            .get(b"MediaBox")
+            .and_then(|o| doc.dereference(o))
+            .map(|(_, o)| o.as_array())
+        {
+            let values = values
+                .iter()
+                .filter_map(|v| v.as_float().ok())
+                .collect::<Vec<_>>();
+            if let [x1, y1, x2, y2] = values[..] {
+                return [x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)];
+            }
+        }
+        node = dict
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .ok();
+    }
+    [0.0, 0.0, 595.0, 842.0]
+}
+
+/// append annotations to page, `/Annots` of page may be an array or a reference to array
+fn append_annotations(
+    doc: &mut Document,
+    page_id: ObjectId,
+    mut ids: Vec<Object>,
+) -> anyhow::Result<()> {
+    let annots = doc.get_dictionary(page_id)?.get(b"Annots").ok().cloned();
+    match annots {
+        Some(Object::Reference(id)) => {
+            doc.get_object_mut(id)?.as_array_mut()?.append(&mut ids);
+        }
+        Some(Object::Array(mut annots)) => {
+            annots.append(&mut ids);
+            doc.get_dictionary_mut(page_id)?.set("Annots", annots);
+        }
+        _ => doc.get_dictionary_mut(page_id)?.set("Annots", ids),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use lopdf::{
+        content::{Content, Operation},
+        Stream,
+    };
+    use nanoid::nanoid;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    pub(crate) struct MockECRF(pub(crate) Vec<(String, usize)>);
+
+    impl ECRF for MockECRF {
+        fn form_page(&self, form: &str) -> Option<usize> {
+            self.0.iter().find(|(f, _)| f.eq(form)).map(|(_, p)| *p)
+        }
+
+        fn list_forms(&self) -> Vec<String> {
+            self.0.iter().map(|(f, _)| f.clone()).collect()
+        }
+    }
+
+    /// pdf of one page for each list of (y, text)
+    pub(crate) fn blank_ecrf(pages: &[&[(i64, &str)]]) -> Document {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+        });
+        let mut kids = vec![];
+        for lines in pages {
+            let mut operations = vec![Operation::new("BT", vec![])];
+            operations.push(Operation::new("Tf", vec!["F1".into(), 10.into()]));
+            for (y, text) in lines.iter() {
+                operations.push(Operation::new(
+                    "Tm",
+                    vec![
+                        1.into(),
+                        0.into(),
+                        0.into(),
+                        1.into(),
+                        50.into(),
+                        (*y).into(),
+                    ],
+                ));
+                operations.push(Operation::new("Tj", vec![Object::string_literal(*text)]));
+            }
+            operations.push(Operation::new("ET", vec![]));
+            let content = Content { operations };
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+            kids.push(Object::Reference(doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Contents" => content_id,
+                "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+            })));
+        }
+        let count = kids.len() as i64;
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => count,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        doc
+    }
+
+    fn row(form: &str, label: &str, domain: &str, variable: &str) -> SpecRow {
+        SpecRow {
+            form: form.into(),
+            label: label.into(),
+            domain: domain.into(),
+            variable: Some(variable.into()),
+            not_submitted: false,
+        }
+    }
+
+    #[test]
+    fn form_pages_test() {
+        let ecrf = MockECRF(vec![("AE".into(), 1), ("VS".into(), 3)]);
+        assert_eq!(ecrf.form_pages("AE", 5), Some(1..=2));
+        assert_eq!(ecrf.form_pages("VS", 5), Some(3..=5));
+        assert_eq!(ecrf.form_pages("DM", 5), None);
+    }
+
+    #[test]
+    fn annotate_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        create_dir_all(&root)?;
+        let source = root.join("blank.pdf");
+        let destination = root.join("annotated.pdf");
+        blank_ecrf(&[
+            &[(700, "Adverse Event Term"), (660, "Serious")],
+            &[(700, "Body   Temperature"), (660, "Pulse")],
+        ])
+        .save(&source)?;
+
+        let mut not_submitted = row("Adverse Events", "Serious", "AE", "AESER");
+        not_submitted.variable = None;
+        not_submitted.not_submitted = true;
+        let spec = vec![
+            row("Adverse Events", "adverse event term", "AE", "AETERM"),
+            not_submitted,
+            row("Adverse Events", "Outcome", "AE", "AEOUT"),
+            row("Vital Signs", "Body Temperature", "VS", "VSORRES"),
+            row("Vital Signs", "Pulse", "SUPPVS", "QVAL"),
+            row("Demographics", "Sex", "DM", "SEX"),
+        ];
+        let ecrf = MockECRF(vec![
+            ("Adverse Events".into(), 1),
+            ("Vital Signs".into(), 2),
+        ]);
+        let report = annotate(AnnotateParam {
+            source: &source,
+            destination: &destination,
+            spec: &spec,
+            ecrf: &ecrf,
+        })?;
+        assert_eq!(report.placed, 4);
+        assert_eq!(report.unplaced.len(), 2);
+        assert_eq!(report.unplaced[0].label, "Outcome");
+        assert_eq!(report.unplaced[1].reason, "form not found in ecrf");
+        report.write_xlsx(root.join("unplaced.xlsx"))?;
+
+        let doc = Document::load(&destination)?;
+        let pages = doc.get_pages();
+        // one domain header and two fields on first page, two headers and two fields on second page
+        assert_eq!(doc.get_page_annotations(pages[&1])?.len(), 3);
+        let annotations = doc.get_page_annotations(pages[&2])?;
+        assert_eq!(annotations.len(), 4);
+        assert_eq!(
+            annotations[1].get(b"C")?.as_array()?[0].as_float()?,
+            DOMAIN_COLOURS[1][0]
+        );
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn annotate_prefix_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        create_dir_all(&root)?;
+        let source = root.join("blank.pdf");
+        let destination = root.join("annotated.pdf");
+        blank_ecrf(&[&[
+            (700, "Sexual Activity"),
+            (680, "Sex:"),
+            (660, "Start Date"),
+            (640, "End Date"),
+            (620, "Visit Date"),
+        ]])
+        .save(&source)?;
+
+        // short labels are listed first and must not take lines of longer labels
+        let spec = vec![
+            row("Demographics", "Sex", "DM", "SEX"),
+            row("Demographics", "Date", "DM", "DMDTC"),
+            row("Demographics", "Sexual Activity", "SUPPDM", "QVAL"),
+            row("Demographics", "Start Date", "DM", "RFSTDTC"),
+            row("Demographics", "Sex", "DM", "SEXCD"),
+        ];
+        let ecrf = MockECRF(vec![("Demographics".into(), 1)]);
+        let report = annotate(AnnotateParam {
+            source: &source,
+            destination: &destination,
+            spec: &spec,
+            ecrf: &ecrf,
+        })?;
+        assert_eq!(report.placed, 3);
+        assert_eq!(report.unplaced.len(), 2);
+        assert_eq!(report.unplaced[0].variable.as_deref(), Some("DMDTC"));
+        assert_eq!(
+            report.unplaced[0].reason,
+            "label matches 2 lines on pages 1-1"
+        );
+        assert_eq!(report.unplaced[1].variable.as_deref(), Some("SEXCD"));
+        assert_eq!(report.unplaced[1].reason, "label not found on pages 1-1");
+
+        let doc = Document::load(&destination)?;
+        let pages = doc.get_pages();
+        let bottom = |text: &str| -> anyhow::Result<f32> {
+            let annotations = doc.get_page_annotations(pages[&1])?;
+            let annotation = annotations
+                .iter()
+                .find(|a| matches!(a.get(b"Contents"), Ok(c) if c.eq(&text_string(text))))
+                .unwrap();
+            Ok(annotation.get(b"Rect")?.as_array()?[1].as_float()?)
+        };
+        assert_eq!(bottom("SEX")?, 680.0 - GAP);
+        assert_eq!(bottom("QVAL")?, 700.0 - GAP);
+        assert_eq!(bottom("RFSTDTC")?, 660.0 - GAP);
+        remove_dir_all(root)?;
+        Ok(())
+    }
+}
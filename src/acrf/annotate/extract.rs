@@ -0,0 +1,283 @@
+use crate::edc::{db::DBStruct, schedule::csv_line};
+use lopdf::{decode_text_string, Document, Object};
+use rust_xlsxwriter::{Format, Workbook};
+use std::{fs, path::Path};
+
+/// FreeText annotation found in an annotated crf
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// form the page belongs to, none for pages before the first form
+    pub form: Option<String>,
+    pub page: usize,
+    pub text: String,
+    pub domain: Option<String>,
+    pub variable: Option<String>,
+    /// rectangle of annotation on page as `[x1, y1, x2, y2]`
+    pub rect: [f32; 4],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationTable {
+    pub annotations: Vec<Annotation>,
+}
+
+const HEADER: [&str; 9] = [
+    "Form",
+    "Page",
+    "Annotation",
+    "Domain",
+    "Variable",
+    "X1",
+    "Y1",
+    "X2",
+    "Y2",
+];
+
+impl AnnotationTable {
+    pub fn to_csv(&self) -> String {
+        let mut lines = Vec::with_capacity(self.annotations.len() + 1);
+        lines.push(csv_line(HEADER.into_iter()));
+        for a in self.annotations.iter() {
+            let numbers = std::iter::once(a.page.to_string())
+                .chain(a.rect.iter().map(|v| v.to_string()))
+                .collect::<Vec<_>>();
+            lines.push(csv_line(
+                [
+                    a.form.as_deref().unwrap_or_default(),
+                    numbers[0].as_str(),
+                    a.text.as_str(),
+                    a.domain.as_deref().unwrap_or_default(),
+                    a.variable.as_deref().unwrap_or_default(),
+                ]
+                .into_iter()
+                .chain(numbers[1..].iter().map(String::as_str)),
+            ));
+        }
+        lines.join("\r\n")
+    }
+
+    pub fn write_csv<P: AsRef<Path>>(&self, p: P) -> anyhow::Result<()> {
+        fs::write(p, self.to_csv())?;
+        Ok(())
+    }
+
+    pub fn write_xlsx<P: AsRef<Path>>(&self, p: P) -> anyhow::Result<()> {
+        let mut workbook = Workbook::new();
+        let header = Format::new().set_bold();
+        let sheet = workbook.add_worksheet();
+        sheet.set_name("Annotations")?;
+        for (col, title) in HEADER.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, *title, &header)?;
+        }
+        for (index, a) in self.annotations.iter().enumerate() {
+            let row = index as u32 + 1;
+            sheet.write_string(row, 0, a.form.as_deref().unwrap_or_default())?;
+            sheet.write_number(row, 1, a.page as f64)?;
+            sheet.write_string(row, 2, &a.text)?;
+            sheet.write_string(row, 3, a.domain.as_deref().unwrap_or_default())?;
+            sheet.write_string(row, 4, a.variable.as_deref().unwrap_or_default())?;
+            for (col, value) in a.rect.iter().enumerate() {
+                sheet.write_number(row, col as u16 + 5, *value as f64)?;
+            }
+        }
+        sheet.set_column_width(0, 30)?;
+        sheet.set_column_width(2, 40)?;
+        workbook.save(p)?;
+        Ok(())
+    }
+}
+
+/// collect FreeText annotations of annotated crf, pages are assigned to forms by start pages of db
+pub fn extract_annotations<P: AsRef<Path>>(
+    source: P,
+    db: &DBStruct,
+) -> anyhow::Result<AnnotationTable> {
+    let doc = Document::load(source.as_ref())?;
+    let mut forms = db.form.iter().collect::<Vec<_>>();
+    forms.sort_by_key(|f| f.page);
+    let mut table = AnnotationTable::default();
+    for (page, page_id) in doc.get_pages() {
+        let page = page as usize;
+        let form = forms
+            .iter()
+            .rev()
+            .find(|f| f.page <= page)
+            .map(|f| f.name.clone());
+        let mut texts = vec![];
+        for annotation in doc.get_page_annotations(page_id)? {
+            if !matches!(
+                annotation.get(b"Subtype").and_then(Object::as_name),
+                Ok(b"FreeText")
+            ) {
+                continue;
+            }
+            let Ok(text) = annotation.get(b"Contents").and_then(decode_text_string) else {
+                continue;
+            };
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            if text.is_empty() {
+                continue;
+            }
+            let mut rect = [0.0; 4];
+            if let Ok(values) = annotation.get(b"Rect").and_then(Object::as_array) {
+                for (v, value) in rect.iter_mut().zip(values) {
+                    *v = value.as_float().unwrap_or_default();
+                }
+            }
+            texts.push((text, rect));
+        }
+        let headers = texts
+            .iter()
+            .filter_map(|(text, _)| domain_header(text))
+            .collect::<Vec<_>>();
+        for (text, rect) in texts {
+            let (domain, variable) = parse_annotation(&text, &headers);
+            table.annotations.push(Annotation {
+                form: form.clone(),
+                page,
+                text,
+                domain,
+                variable,
+                rect,
+            });
+        }
+    }
+    Ok(table)
+}
+
+/// whether token looks like a domain, such as `AE`, `SUPPAE` or `RELREC`
+fn is_domain(token: &str) -> bool {
+    let core = token.strip_prefix("SUPP").unwrap_or(token);
+    token.eq("RELREC") || (core.len() == 2 && core.chars().all(|c| c.is_ascii_uppercase()))
+}
+
+/// domain of header annotation, such as `AE (Adverse Events)` or `DM=Demographics`
+fn domain_header(text: &str) -> Option<String> {
+    let end = text
+        .find(|c: char| c.is_whitespace() || c == '(' || c == '=')
+        .unwrap_or(text.len());
+    let token = &text[..end];
+    let rest = text[end..].trim_start();
+    (is_domain(token) && (rest.is_empty() || rest.starts_with(['(', '='])))
+        .then(|| token.to_string())
+}
+
+/// parse domain and variable of annotation, domain of a variable is taken from
+/// `DOMAIN.VARIABLE`, `VARIABLE in DOMAIN` or the domain headers on the same page
+fn parse_annotation(text: &str, headers: &[String]) -> (Option<String>, Option<String>) {
+    if text.to_uppercase().contains("NOT SUBMITTED") {
+        return (None, None);
+    }
+    if let Some(domain) = domain_header(text) {
+        return (Some(domain), None);
+    }
+    let mut words = text.split_whitespace();
+    let Some(token) = words.next().map(|w| w.trim_end_matches([',', ';', ':'])) else {
+        return (None, None);
+    };
+    if !token
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_' || c == '.')
+    {
+        return (None, None);
+    }
+    if let Some((domain, variable)) = token.split_once('.') {
+        return (Some(domain.to_string()), Some(variable.to_string()));
+    }
+    let variable = Some(token.to_string());
+    if let (Some("in"), Some(domain)) = (words.next(), words.next()) {
+        if is_domain(domain) {
+            return (Some(domain.to_string()), variable);
+        }
+    }
+    let domain = headers
+        .iter()
+        .filter(|h| token.starts_with(h.as_str()))
+        .max_by_key(|h| h.len())
+        .cloned();
+    (domain, variable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        acrf::annotate::{
+            annotate,
+            spec::SpecRow,
+            tests::{blank_ecrf, MockECRF},
+            AnnotateParam,
+        },
+        edc::db::Form,
+    };
+    use nanoid::nanoid;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    #[test]
+    fn parse_annotation_test() {
+        let headers = vec!["AE".to_string(), "SUPPAE".to_string()];
+        let parse = |text| parse_annotation(text, &headers);
+        assert_eq!(parse("AE (Adverse Events)"), (Some("AE".into()), None));
+        assert_eq!(parse("AETERM"), (Some("AE".into()), Some("AETERM".into())));
+        assert_eq!(
+            parse("VSORRES when VSTESTCD = TEMP"),
+            (None, Some("VSORRES".into()))
+        );
+        assert_eq!(
+            parse("QVAL in SUPPAE"),
+            (Some("SUPPAE".into()), Some("QVAL".into()))
+        );
+        assert_eq!(parse("DM.SEX"), (Some("DM".into()), Some("SEX".into())));
+        assert_eq!(parse("[NOT SUBMITTED]"), (None, None));
+        assert_eq!(parse("see page 3"), (None, None));
+    }
+
+    #[test]
+    fn extract_annotations_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        create_dir_all(&root)?;
+        let source = root.join("blank.pdf");
+        let annotated = root.join("annotated.pdf");
+        blank_ecrf(&[&[], &[(700, "Adverse Event Term")]]).save(&source)?;
+        let spec = vec![SpecRow {
+            form: "Adverse Events".into(),
+            label: "Adverse Event Term".into(),
+            domain: "AE".into(),
+            variable: Some("AETERM".into()),
+            not_submitted: false,
+        }];
+        annotate(AnnotateParam {
+            source: &source,
+            destination: &annotated,
+            spec: &spec,
+            ecrf: &MockECRF(vec![("Adverse Events".into(), 2)]),
+        })?;
+
+        let db = DBStruct {
+            visit: vec![],
+            form: vec![Form {
+                id: 0,
+                name: "Adverse Events".into(),
+                page: 2,
+                order: 0,
+                translation: None,
+                category: None,
+                oid: None,
+                hidden: false,
+                imported: None,
+            }],
+            binding: vec![],
+        };
+        let table = extract_annotations(&annotated, &db)?;
+        assert_eq!(table.annotations.len(), 2);
+        let field = &table.annotations[1];
+        assert_eq!(field.form.as_deref(), Some("Adverse Events"));
+        assert_eq!(field.page, 2);
+        assert_eq!(field.domain.as_deref(), Some("AE"));
+        assert_eq!(field.variable.as_deref(), Some("AETERM"));
+        assert!(table.to_csv().starts_with("Form,Page,Annotation"));
+        table.write_xlsx(root.join("annotations.xlsx"))?;
+        remove_dir_all(root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::bail;
+use calamine::{open_workbook, Data, DataType, Range, Reader, Xlsx};
+use std::path::Path;
+
+/// value of variable column marking a field as not submitted
+const NOT_SUBMITTED: &str = "NOT SUBMITTED";
+
+/// one field of sdtm mapping spec
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecRow {
+    pub form: String,
+    /// label of field as printed in ecrf
+    pub label: String,
+    pub domain: String,
+    pub variable: Option<String>,
+    pub not_submitted: bool,
+}
+
+impl SpecRow {
+    /// text of annotation, such as `AETERM` or `[NOT SUBMITTED]`
+    pub fn annotation(&self) -> String {
+        if self.not_submitted {
+            format!("[{}]", NOT_SUBMITTED)
+        } else {
+            self.variable.clone().unwrap_or_else(|| self.domain.clone())
+        }
+    }
+}
+
+/// read mapping spec from the first sheet of workbook
+pub fn read_spec<P: AsRef<Path>>(p: P) -> anyhow::Result<Vec<SpecRow>> {
+    let mut workbook: Xlsx<_> = open_workbook(p)?;
+    let sheet = workbook
+        .worksheet_range_at(0)
+        .unwrap_or(Ok(Range::default()))?;
+    spec_rows(&sheet)
+}
+
+/// first row is header, columns are found by name regardless of case:
+/// Form, Field Label (or Label), Domain, Variable and optional Not Submitted
+pub(crate) fn spec_rows(sheet: &Range<Data>) -> anyhow::Result<Vec<SpecRow>> {
+    let mut rows = sheet.rows();
+    let header = rows
+        .next()
+        .map(|row| {
+            row.iter()
+                .map(|c| c.as_string().unwrap_or_default().trim().to_lowercase())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+    let (Some(form), Some(label), Some(domain)) = (
+        column(&["form"]),
+        column(&["field label", "label"]),
+        column(&["domain"]),
+    ) else {
+        bail!("spec should contain columns of Form, Field Label and Domain");
+    };
+    let variable = column(&["variable"]);
+    let not_submitted = column(&["not submitted"]);
+
+    let cell = |row: &[Data], index: Option<usize>| {
+        index
+            .and_then(|i| row.get(i))
+            .and_then(|c| c.as_string())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    };
+    let mut spec = vec![];
+    for row in rows {
+        let (Some(form), Some(label), Some(domain)) = (
+            cell(row, Some(form)),
+            cell(row, Some(label)),
+            cell(row, Some(domain)),
+        ) else {
+            continue;
+        };
+        let variable = cell(row, variable);
+        let flagged = cell(row, not_submitted)
+            .map(|s| matches!(s.to_uppercase().as_str(), "Y" | "YES" | "TRUE" | "X" | "1"))
+            .unwrap_or(false);
+        let not_submitted = flagged
+            || variable
+                .as_deref()
+                .map(|v| v.eq_ignore_ascii_case(NOT_SUBMITTED))
+                == Some(true);
+        spec.push(SpecRow {
+            form,
+            label,
+            domain: domain.to_uppercase(),
+            variable: variable.filter(|_| !not_submitted),
+            not_submitted,
+        });
+    }
+    Ok(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_rows_test() -> anyhow::Result<()> {
+        let mut sheet = Range::new((0, 0), (3, 4));
+        for (col, title) in ["Form", "Field Label", "Domain", "Variable", "Not Submitted"]
+            .iter()
+            .enumerate()
+        {
+            sheet.set_value((0, col as u32), Data::String(title.to_string()));
+        }
+        let rows = [
+            ["不良事件", "不良事件名称", "ae", "AETERM", ""],
+            ["不良事件", "是否严重", "AE", "NOT SUBMITTED", ""],
+            ["生命体征", "体温", "VS", "VSORRES", "Y"],
+        ];
+        for (r, row) in rows.iter().enumerate() {
+            for (c, value) in row.iter().enumerate() {
+                sheet.set_value((r as u32 + 1, c as u32), Data::String(value.to_string()));
+            }
+        }
+        let spec = spec_rows(&sheet)?;
+        assert_eq!(spec.len(), 3);
+        assert_eq!(spec[0].domain, "AE");
+        assert_eq!(spec[0].annotation(), "AETERM");
+        assert!(spec[1].not_submitted);
+        assert_eq!(spec[1].variable, None);
+        assert!(spec[2].not_submitted);
+        assert_eq!(spec[2].annotation(), "[NOT SUBMITTED]");
+
+        assert!(spec_rows(&Range::new((0, 0), (0, 0))).is_err());
+        Ok(())
+    }
+}
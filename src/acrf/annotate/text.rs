@@ -0,0 +1,217 @@
+use lopdf::{content::Content, Document, Encoding, Object, ObjectId};
+use std::collections::BTreeMap;
+
+/// line of text on page, coordinates are in default user space
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TextLine {
+    pub(crate) text: String,
+    pub(crate) left: f32,
+    pub(crate) right: f32,
+    /// baseline of line
+    pub(crate) y: f32,
+    pub(crate) size: f32,
+}
+
+/// piece of text shown by one text operator
+#[derive(Debug, Clone)]
+struct TextRun {
+    text: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    size: f32,
+}
+
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// `m` applied before `n`
+fn multiply(m: &Matrix, n: &Matrix) -> Matrix {
+    [
+        m[0] * n[0] + m[1] * n[2],
+        m[0] * n[1] + m[1] * n[3],
+        m[2] * n[0] + m[3] * n[2],
+        m[2] * n[1] + m[3] * n[3],
+        m[4] * n[0] + m[5] * n[2] + n[4],
+        m[4] * n[1] + m[5] * n[3] + n[5],
+    ]
+}
+
+fn translate(tx: f32, ty: f32) -> Matrix {
+    [1.0, 0.0, 0.0, 1.0, tx, ty]
+}
+
+/// state of content stream interpreter, only what is needed to position text
+struct TextState<'a> {
+    encodings: BTreeMap<Vec<u8>, Encoding<'a>>,
+    ctm: Matrix,
+    stack: Vec<Matrix>,
+    tm: Matrix,
+    tlm: Matrix,
+    font: Vec<u8>,
+    size: f32,
+    leading: f32,
+    runs: Vec<TextRun>,
+}
+
+impl TextState<'_> {
+    fn next_line(&mut self, tx: f32, ty: f32) {
+        self.tlm = multiply(&translate(tx, ty), &self.tlm);
+        self.tm = self.tlm;
+    }
+
+    fn show(&mut self, bytes: &[u8]) {
+        let text = match self.encodings.get(&self.font) {
+            Some(encoding) => Document::decode_text(encoding, bytes).unwrap_or_default(),
+            None => String::from_utf8_lossy(bytes).to_string(),
+        };
+        // glyph widths are not read from fonts, wide characters take a full em and others half of it
+        let advance = text
+            .chars()
+            .map(|c| if c.is_ascii() { 0.5 } else { 1.0 })
+            .sum::<f32>()
+            * self.size;
+        let trm = multiply(&self.tm, &self.ctm);
+        let scale = (trm[2] * trm[2] + trm[3] * trm[3]).sqrt();
+        let width = advance * (trm[0] * trm[0] + trm[1] * trm[1]).sqrt();
+        self.runs.push(TextRun {
+            text,
+            x: trm[4],
+            y: trm[5],
+            width,
+            size: self.size * scale,
+        });
+        self.tm = multiply(&translate(advance, 0.0), &self.tm);
+    }
+
+    /// number in TJ array moves next glyph left by thousandths of text size
+    fn adjust(&mut self, amount: f32) {
+        self.tm = multiply(&translate(-amount / 1000.0 * self.size, 0.0), &self.tm);
+    }
+}
+
+/// lines of text on page, ordered from top to bottom
+pub(crate) fn page_lines(doc: &Document, page_id: ObjectId) -> anyhow::Result<Vec<TextLine>> {
+    let encodings = doc
+        .get_page_fonts(page_id)?
+        .into_iter()
+        .filter_map(|(name, font)| font.get_font_encoding(doc).ok().map(|e| (name, e)))
+        .collect();
+    let content = Content::decode(&doc.get_page_content(page_id)?)?;
+    let mut state = TextState {
+        encodings,
+        ctm: IDENTITY,
+        stack: vec![],
+        tm: IDENTITY,
+        tlm: IDENTITY,
+        font: vec![],
+        size: 0.0,
+        leading: 0.0,
+        runs: vec![],
+    };
+    for operation in content.operations.iter() {
+        let operands = &operation.operands;
+        let number = |i: usize| {
+            operands
+                .get(i)
+                .and_then(|o| o.as_float().ok())
+                .unwrap_or(0.0)
+        };
+        let matrix = || -> Matrix { [0, 1, 2, 3, 4, 5].map(number) };
+        match operation.operator.as_str() {
+            "q" => state.stack.push(state.ctm),
+            "Q" => state.ctm = state.stack.pop().unwrap_or(IDENTITY),
+            "cm" => state.ctm = multiply(&matrix(), &state.ctm),
+            "BT" => {
+                state.tm = IDENTITY;
+                state.tlm = IDENTITY;
+            }
+            "Tf" => {
+                if let Some(Ok(name)) = operands.first().map(Object::as_name) {
+                    state.font = name.to_vec();
+                }
+                state.size = number(1);
+            }
+            "TL" => state.leading = number(0),
+            "Td" => state.next_line(number(0), number(1)),
+            "TD" => {
+                state.leading = -number(1);
+                state.next_line(number(0), number(1));
+            }
+            "Tm" => {
+                state.tlm = matrix();
+                state.tm = state.tlm;
+            }
+            "T*" => state.next_line(0.0, -state.leading),
+            "Tj" | "'" | "\"" => {
+                if operation.operator.ne("Tj") {
+                    state.next_line(0.0, -state.leading);
+                }
+                if let Some(Object::String(bytes, _)) = operands.last() {
+                    state.show(bytes);
+                }
+            }
+            "TJ" => {
+                if let Some(Ok(items)) = operands.first().map(Object::as_array) {
+                    for item in items {
+                        match item {
+                            Object::String(bytes, _) => state.show(bytes),
+                            item => state.adjust(item.as_float().unwrap_or(0.0)),
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(group_lines(state.runs))
+}
+
+/// runs with baselines closer than half of their size are on the same line
+fn group_lines(mut runs: Vec<TextRun>) -> Vec<TextLine> {
+    runs.retain(|r| !r.text.trim().is_empty());
+    runs.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+    let mut lines: Vec<(Vec<TextRun>, f32)> = vec![];
+    for run in runs {
+        match lines
+            .iter_mut()
+            .find(|(_, y)| (y - run.y).abs() <= run.size.max(1.0) / 2.0)
+        {
+            Some((line, _)) => line.push(run),
+            None => {
+                let y = run.y;
+                lines.push((vec![run], y));
+            }
+        }
+    }
+    lines
+        .into_iter()
+        .map(|(mut runs, y)| {
+            runs.sort_by(|a, b| a.x.total_cmp(&b.x));
+            TextLine {
+                text: runs.iter().map(|r| r.text.as_str()).collect(),
+                left: runs.first().map(|r| r.x).unwrap_or_default(),
+                right: runs.iter().map(|r| r.x + r.width).fold(0.0, f32::max),
+                y,
+                size: runs.iter().map(|r| r.size).fold(0.0, f32::max),
+            }
+        })
+        .collect()
+}
+
+/// lowercase text without whitespace, so that labels broken into runs still match
+pub(crate) fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// lowercase words of text, punctuation separates words
+pub(crate) fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
@@ -1,87 +1,160 @@
-use super::{combiner::merge_pdf, toc::render::Level};
+use super::{
+    check::{check, CheckOptions, CheckReport},
+    combiner::merge_pdf,
+    label::{write_page_labels, PageLabels},
+    linker::LinkReport,
+    toc::render::Level,
+    view::{write_metadata, write_view, Metadata, ViewOptions},
+};
 use crate::{
-    acrf::toc::render::{Render, RenderData, RenderParam},
-    edc::db::{DBStruct, Form, FormVisitBinding, Visit},
+    acrf::annotate::extract::{extract_annotations, Annotation},
+    acrf::toc::{
+        layout::{FormGroup, SectionKind, TocLayout},
+        locale::TocLocale,
+        render::{Render, RenderData, RenderParam},
+        template::TocTemplate,
+    },
+    edc::{
+        db::{DBStruct, Form, FormVisitBinding, Visit},
+        schedule::Schedule,
+    },
 };
+use anyhow::bail;
 use lopdf::Document;
 use std::{cell::Cell, collections::HashMap, path::Path};
 
 pub struct ACrfBuilder {
+    pub(crate) db: DBStruct,
     pub(crate) visit_map: HashMap<usize, Visit>,
     pub(crate) form_map: HashMap<usize, Form>,
     pub(crate) visit_form_binding: HashMap<usize, Vec<usize>>,
     pub(crate) form_visit_binding: HashMap<usize, Vec<usize>>,
     pub(crate) toc_pages: Cell<usize>,
-    pub(crate) visit_render_data: Option<RenderData>,
-    pub(crate) form_render_data: Option<RenderData>,
+    pub(crate) render_data: Vec<RenderData>,
+    /// annotations of source, scanned only if layout contains domain section
+    pub(crate) annotations: Vec<Annotation>,
 }
 
 impl ACrfBuilder {
     pub fn new(db: DBStruct) -> Self {
         let visit_map = db.visit.iter().map(|v: &Visit| (v.id, v.clone())).collect();
         let form_map = db.form.iter().map(|f| (f.id, f.clone())).collect();
-        let form_visit_binding = db
-            .binding
-            .iter()
-            .map(|f| (f.parent, f.children.clone()))
-            .collect();
+        // visits of bindings of the same form are merged, each visit is kept once
+        let mut form_visit_binding: HashMap<usize, Vec<usize>> =
+            HashMap::with_capacity(db.binding.len());
         let mut visit_form_binding: HashMap<usize, Vec<usize>> =
             HashMap::with_capacity(db.visit.len());
         db.binding
             .iter()
             .for_each(|FormVisitBinding { parent, children }| {
+                let visits = form_visit_binding.entry(*parent).or_default();
                 children.iter().for_each(|v| {
-                    if let Some(children) = visit_form_binding.get_mut(v) {
-                        children.push(*parent);
-                    } else {
-                        visit_form_binding.insert(*v, vec![*parent]);
+                    if visits.contains(v) {
+                        return;
                     }
+                    visits.push(*v);
+                    visit_form_binding.entry(*v).or_default().push(*parent);
                 });
             });
         ACrfBuilder {
+            db,
             visit_map,
             form_map,
             visit_form_binding,
             form_visit_binding,
             toc_pages: Cell::new(0),
-            visit_render_data: None,
-            form_render_data: None,
+            render_data: vec![],
+            annotations: vec![],
         }
     }
 
-    pub fn build<P: AsRef<Path>>(&mut self, param: BuildParam<P>) -> anyhow::Result<()> {
+    /// build acrf with toc and bookmarks, return report of links verified after building and
+    /// of conformance checks of the built pdf, such as bookmarks nested deeper than allowed
+    pub fn build<P: AsRef<Path>>(&mut self, param: BuildParam<P>) -> anyhow::Result<BuildReport> {
         let BuildParam {
             source,
             destination,
             workspace,
             bookmark_bin,
+            options,
         } = param;
-        self.visit_render_data = Some(self.build_visit_render_data());
-        self.form_render_data = Some(self.build_form_render_data());
+        let source_pages = Document::load(source.as_ref())?.get_pages().len();
+        let errors = self
+            .db
+            .validate(Some(source_pages))
+            .into_iter()
+            .filter(|d| d.is_error())
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            bail!("invalid db struct:\n{}", errors.join("\n"));
+        }
+        if options.layout.sections.is_empty() {
+            bail!("toc layout contains no section");
+        }
+        if options
+            .layout
+            .sections
+            .iter()
+            .any(|s| matches!(s.kind, SectionKind::Domain))
+        {
+            self.annotations = extract_annotations(source.as_ref(), &self.db)?.annotations;
+        }
+        self.render_data = self.build_render_data(&options.layout, &options.locale);
+        if self.render_data.is_empty() {
+            bail!("toc is empty, sections of toc layout have no entries");
+        }
+        if let Some(label) = options.page_labels.body.as_ref() {
+            for data in self.render_data.iter_mut() {
+                data.update_labels(label);
+            }
+        }
 
         // build toc
         let toc = workspace.as_ref().join("toc.pdf");
-        self.build_toc(&toc)?;
+        let schedule = if options.schedule {
+            Some(Schedule::new(&self.db).to_html_table())
+        } else {
+            None
+        };
+        self.build_toc(&toc, &options.template, schedule)?;
         self.update_toc_pages(&toc)?;
         // merge toc to acrf
         merge_pdf(&vec![&toc, source.as_ref()], destination.as_ref())?;
         // link toc
-        self.link_toc(destination.as_ref())?;
+        let (links, issues) = self.link_toc(destination.as_ref())?;
         // link bookmarks
         self.link_bookmark(LinkBookmarkParam {
             target: destination.as_ref(),
             acrf_outline_bin: bookmark_bin.as_ref(),
             workspace: workspace.as_ref(),
         })?;
-        Ok(())
+        self.link_names(destination.as_ref())?;
+        // label pages, set how document opens and its properties
+        let mut document = Document::load(destination.as_ref())?;
+        write_page_labels(&mut document, self.toc_pages.get(), &options.page_labels);
+        write_view(&mut document, &options.view)?;
+        write_metadata(&mut document, &options.metadata)?;
+        document.save(destination.as_ref())?;
+        let mut links_report = self.verify_links(destination.as_ref(), &links)?;
+        links_report.issues.splice(0..0, issues);
+        Ok(BuildReport {
+            links: links_report,
+            check: check(destination.as_ref(), &options.check)?,
+        })
     }
 
-    fn build_toc<P: AsRef<Path>>(&self, destination: P) -> anyhow::Result<()> {
-        if self.visit_render_data.is_some() || self.form_render_data.is_some() {
-            let render = Render::new()?;
+    fn build_toc<P: AsRef<Path>>(
+        &self,
+        destination: P,
+        template: &TocTemplate,
+        schedule: Option<String>,
+    ) -> anyhow::Result<()> {
+        if !self.render_data.is_empty() {
+            let render = Render::new(template)?;
             render.write(RenderParam {
-                visit: self.visit_render_data.clone().unwrap(),
-                form: self.form_render_data.clone().unwrap(),
+                toc: self.render_data.clone(),
+                schedule,
                 destination,
             })?;
         }
@@ -94,87 +167,299 @@ impl ACrfBuilder {
         Ok(())
     }
 
-    fn build_visit_render_data(&self) -> RenderData {
-        let mut data = RenderData::default();
-        data.name = "Visit".into();
+    pub(crate) fn build_render_data(
+        &self,
+        layout: &TocLayout,
+        locale: &TocLocale,
+    ) -> Vec<RenderData> {
+        layout
+            .sections
+            .iter()
+            .filter_map(|section| {
+                let title = section
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| locale.section_title(&section.kind));
+                match &section.kind {
+                    SectionKind::Visit => {
+                        Some(self.build_visit_render_data(&title, layout.categories, locale))
+                    }
+                    SectionKind::Form => {
+                        Some(self.build_form_render_data(&title, layout.categories, locale))
+                    }
+                    SectionKind::Group(groups) => {
+                        Some(self.build_group_render_data(&title, groups, locale))
+                    }
+                    SectionKind::Domain => self.build_domain_render_data(&title, locale),
+                }
+            })
+            .collect()
+    }
+
+    fn build_visit_render_data(
+        &self,
+        title: &str,
+        categories: bool,
+        locale: &TocLocale,
+    ) -> RenderData {
         let mut data_children = Vec::with_capacity(self.visit_map.len());
         let mut visits = self
             .visit_map
             .values()
+            .filter(|v| !v.hidden)
             .map(|v| v.clone())
             .collect::<Vec<_>>();
         visits.sort_by(|x, y| x.order.cmp(&y.order));
         for visit in visits {
-            let mut visit_data = RenderData::default();
-            visit_data.name = visit.name.clone();
-            visit_data.kind = Level::LEVEL2;
             if let Some(forms) = self.visit_form_binding.get(&visit.id) {
                 let mut form_list = Vec::with_capacity(forms.len());
                 for form_id in forms {
                     if let Some(form) = self.form_map.get(form_id) {
-                        form_list.push(form.clone());
+                        if !form.hidden {
+                            form_list.push(form.clone());
+                        }
                     }
                 }
+                if form_list.is_empty() {
+                    continue;
+                }
                 form_list.sort_by(|x, y| x.order.cmp(&y.order));
-                let visit_data_children = form_list
-                    .into_iter()
-                    .map(|f| {
-                        let mut form_data = RenderData::default();
-                        form_data.id = Some(f.id);
-                        form_data.name = f.name.clone();
-                        form_data.kind = Level::LEVEL3;
-                        form_data.page = Some(f.page);
-                        form_data
-                    })
-                    .collect::<Vec<_>>();
-                visit_data.children = Some(visit_data_children);
-                data_children.push(visit_data);
+                let mut visit_data_children = Vec::with_capacity(form_list.len());
+                for (category, forms) in group_by_category(form_list, categories) {
+                    let kind = if category.is_some() {
+                        Level::LEVEL4
+                    } else {
+                        Level::LEVEL3
+                    };
+                    let form_data_list = forms
+                        .into_iter()
+                        .map(|f| RenderData {
+                            id: Some(f.id),
+                            name: locale.display_name(&f.name, f.translation.as_deref()),
+                            kind: kind.clone(),
+                            page: Some(f.page),
+                            ..Default::default()
+                        })
+                        .collect::<Vec<_>>();
+                    match category {
+                        Some(category) => visit_data_children.push(RenderData {
+                            name: category,
+                            kind: Level::LEVEL3,
+                            children: Some(form_data_list),
+                            ..Default::default()
+                        }),
+                        None => visit_data_children.extend(form_data_list),
+                    }
+                }
+                data_children.push(RenderData {
+                    name: locale.display_name(&visit.name, visit.translation.as_deref()),
+                    kind: Level::LEVEL2,
+                    children: Some(visit_data_children),
+                    ..Default::default()
+                });
             }
         }
-        data.children = Some(data_children);
-        data
+        RenderData {
+            name: title.into(),
+            children: Some(data_children),
+            ..Default::default()
+        }
     }
 
-    fn build_form_render_data(&self) -> RenderData {
-        let mut data = RenderData::default();
-        data.name = "Forms".into();
+    fn build_form_render_data(
+        &self,
+        title: &str,
+        categories: bool,
+        locale: &TocLocale,
+    ) -> RenderData {
         let mut data_children = Vec::with_capacity(self.form_map.len());
         let mut forms = self
             .form_map
             .values()
+            .filter(|f| !f.hidden)
             .map(|f| f.clone())
             .collect::<Vec<_>>();
         forms.sort_by(|x, y| x.order.cmp(&y.order));
-        for form in forms {
-            let mut form_data = RenderData::default();
-            form_data.kind = Level::LEVEL2;
-            form_data.name = form.name.clone();
-            if let Some(visits) = self.form_visit_binding.get(&form.id) {
-                let mut visit_list = Vec::with_capacity(visits.len());
-                for visit_id in visits {
-                    if let Some(visit) = self.visit_map.get(visit_id) {
-                        visit_list.push(visit.clone());
+        for (category, forms) in group_by_category(forms, categories) {
+            let (form_kind, visit_kind) = if category.is_some() {
+                (Level::LEVEL3, Level::LEVEL4)
+            } else {
+                (Level::LEVEL2, Level::LEVEL3)
+            };
+            let mut form_data_list = Vec::with_capacity(forms.len());
+            for form in forms {
+                if let Some(visits) = self.form_visit_binding.get(&form.id) {
+                    let mut visit_list = Vec::with_capacity(visits.len());
+                    for visit_id in visits {
+                        if let Some(visit) = self.visit_map.get(visit_id) {
+                            if !visit.hidden {
+                                visit_list.push(visit.clone());
+                            }
+                        }
+                    }
+                    if visit_list.is_empty() {
+                        continue;
+                    }
+                    visit_list.sort_by(|x, y| x.order.cmp(&y.order));
+                    let form_data_children = visit_list
+                        .into_iter()
+                        .map(|v| RenderData {
+                            id: Some(form.id),
+                            name: locale.display_name(&v.name, v.translation.as_deref()),
+                            kind: visit_kind.clone(),
+                            page: Some(form.page),
+                            ..Default::default()
+                        })
+                        .collect::<Vec<_>>();
+                    form_data_list.push(RenderData {
+                        kind: form_kind.clone(),
+                        name: locale.display_name(&form.name, form.translation.as_deref()),
+                        children: Some(form_data_children),
+                        ..Default::default()
+                    });
+                }
+            }
+            match category {
+                Some(category) => {
+                    if form_data_list.is_empty() {
+                        continue;
                     }
+                    data_children.push(RenderData {
+                        name: category,
+                        kind: Level::LEVEL2,
+                        children: Some(form_data_list),
+                        ..Default::default()
+                    });
                 }
-                visit_list.sort_by(|x, y| x.order.cmp(&y.order));
-                let form_data_children = visit_list
-                    .into_iter()
-                    .map(|v| {
-                        let mut visit_data = RenderData::default();
-                        visit_data.id = Some(form.id);
-                        visit_data.name = v.name.clone();
-                        visit_data.kind = Level::LEVEL3;
-                        visit_data.page = Some(form.page);
-                        visit_data
+                None => data_children.extend(form_data_list),
+            }
+        }
+        RenderData {
+            name: title.into(),
+            children: Some(data_children),
+            ..Default::default()
+        }
+    }
+
+    fn build_group_render_data(
+        &self,
+        title: &str,
+        groups: &[FormGroup],
+        locale: &TocLocale,
+    ) -> RenderData {
+        let mut data_children = Vec::with_capacity(groups.len());
+        for group in groups {
+            let mut form_list = group
+                .forms
+                .iter()
+                .filter_map(|id| self.form_map.get(id))
+                .filter(|f| !f.hidden)
+                .collect::<Vec<_>>();
+            if form_list.is_empty() {
+                continue;
+            }
+            form_list.sort_by_key(|f| f.order);
+            let group_data_children = form_list
+                .into_iter()
+                .map(|f| RenderData {
+                    id: Some(f.id),
+                    name: locale.display_name(&f.name, f.translation.as_deref()),
+                    kind: Level::LEVEL3,
+                    page: Some(f.page),
+                    ..Default::default()
+                })
+                .collect::<Vec<_>>();
+            data_children.push(RenderData {
+                kind: Level::LEVEL2,
+                name: group.name.clone(),
+                children: Some(group_data_children),
+                ..Default::default()
+            });
+        }
+        RenderData {
+            name: title.into(),
+            children: Some(data_children),
+            ..Default::default()
+        }
+    }
+
+    /// domain -> forms of pages annotated with the domain, pages after the first page of form
+    /// are named with their index in form, each entry keeps its own page so that its toc link
+    /// and bookmark land on that page, return none if no domain is annotated
+    fn build_domain_render_data(&self, title: &str, locale: &TocLocale) -> Option<RenderData> {
+        let mut domains: Vec<(&str, Vec<usize>)> = vec![];
+        for annotation in self.annotations.iter() {
+            let Some(domain) = annotation.domain.as_deref() else {
+                continue;
+            };
+            match domains.iter_mut().find(|(d, _)| d.eq(&domain)) {
+                Some((_, pages)) => pages.push(annotation.page),
+                None => domains.push((domain, vec![annotation.page])),
+            }
+        }
+        domains.sort_by_key(|(domain, _)| *domain);
+        let mut forms = self.form_map.values().collect::<Vec<_>>();
+        forms.sort_by_key(|f| f.page);
+
+        let mut data_children = Vec::with_capacity(domains.len());
+        for (domain, mut pages) in domains {
+            pages.sort();
+            pages.dedup();
+            let page_data_list = pages
+                .into_iter()
+                .filter_map(|page| {
+                    let form = forms.iter().rev().find(|f| f.page <= page)?;
+                    if form.hidden {
+                        return None;
+                    }
+                    let name = locale.display_name(&form.name, form.translation.as_deref());
+                    Some(RenderData {
+                        id: Some(form.id),
+                        name: match page - form.page {
+                            0 => name,
+                            offset => format!("{} ({})", name, offset + 1),
+                        },
+                        kind: Level::LEVEL3,
+                        page: Some(page),
+                        ..Default::default()
                     })
-                    .collect::<Vec<_>>();
-                form_data.children = Some(form_data_children);
-                data_children.push(form_data);
+                })
+                .collect::<Vec<_>>();
+            if page_data_list.is_empty() {
+                continue;
             }
+            data_children.push(RenderData {
+                name: domain.into(),
+                kind: Level::LEVEL2,
+                children: Some(page_data_list),
+                ..Default::default()
+            });
+        }
+        if data_children.is_empty() {
+            return None;
+        }
+        Some(RenderData {
+            name: title.into(),
+            children: Some(data_children),
+            ..Default::default()
+        })
+    }
+}
+
+/// group sorted forms by category, keep the order of first appearance of each category
+fn group_by_category(forms: Vec<Form>, enabled: bool) -> Vec<(Option<String>, Vec<Form>)> {
+    if !enabled {
+        return vec![(None, forms)];
+    }
+    let mut groups: Vec<(Option<String>, Vec<Form>)> = vec![];
+    for form in forms {
+        let category = form.category.clone();
+        match groups.iter_mut().find(|(c, _)| category.eq(c)) {
+            Some((_, group)) => group.push(form),
+            None => groups.push((category, vec![form])),
         }
-        data.children = Some(data_children);
-        data
     }
+    groups
 }
 
 pub struct BuildParam<P: AsRef<Path>> {
@@ -182,6 +467,38 @@ pub struct BuildParam<P: AsRef<Path>> {
     pub destination: P,
     pub workspace: P,
     pub bookmark_bin: P,
+    pub options: BuildOptions,
+}
+
+/// optional settings of building, use `BuildOptions::default()` for the built in behaviour
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    pub template: TocTemplate,
+    pub layout: TocLayout,
+    pub locale: TocLocale,
+    /// append schedule of assessments after toc sections
+    pub schedule: bool,
+    /// page labels of toc and crf pages, also shown in toc entries
+    pub page_labels: PageLabels,
+    pub view: ViewOptions,
+    pub metadata: Metadata,
+    /// rules the built pdf is checked against, the default layout nests bookmarks 3 levels
+    /// deep, or 4 if forms have category, so `max_bookmark_depth` has to be relaxed to pass
+    /// with it
+    pub check: CheckOptions,
+}
+
+/// links verified after building and conformance of the built pdf
+#[derive(Debug, Clone)]
+pub struct BuildReport {
+    pub links: LinkReport,
+    pub check: CheckReport,
+}
+
+impl BuildReport {
+    pub fn is_ok(&self) -> bool {
+        self.links.is_ok() && self.check.passed()
+    }
 }
 
 pub struct LinkBookmarkParam<P: AsRef<Path>> {
@@ -189,3 +506,200 @@ pub struct LinkBookmarkParam<P: AsRef<Path>> {
     pub(crate) acrf_outline_bin: P,
     pub(crate) workspace: P,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        acrf::{
+            annotate::tests::blank_ecrf,
+            label::{LabelStyle, PageLabel},
+            toc::layout::TocSection,
+        },
+        edc::db::tests::{bind, db, visit},
+    };
+    use nanoid::nanoid;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    #[test]
+    fn build_render_data_test() {
+        let builder = ACrfBuilder::new(db());
+        let locale = TocLocale::default();
+        let data = builder.build_render_data(&TocLayout::default(), &locale);
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0].name, "Visit");
+        assert_eq!(data[1].name, "Forms");
+
+        let data = builder.build_render_data(&TocLayout::forms_only(), &locale);
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].name, "Forms");
+
+        let layout = TocLayout {
+            sections: vec![
+                TocSection::form("By Form"),
+                TocSection::group(
+                    "Domains",
+                    vec![
+                        FormGroup {
+                            name: "VS".into(),
+                            forms: vec![0],
+                        },
+                        FormGroup {
+                            name: "DM".into(),
+                            forms: vec![1, 99],
+                        },
+                        FormGroup {
+                            name: "LB".into(),
+                            forms: vec![99],
+                        },
+                    ],
+                ),
+            ],
+            categories: true,
+        };
+        let data = builder.build_render_data(&layout, &locale);
+        assert_eq!(data[0].name, "By Form");
+        let groups = data[1].children.as_ref().unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[1].name, "DM");
+        let forms = groups[1].children.as_ref().unwrap();
+        assert_eq!(forms.len(), 1);
+        assert_eq!(forms[0].id, Some(1));
+        assert_eq!(forms[0].page, Some(1));
+
+        let mut data = data;
+        let label = PageLabel {
+            style: LabelStyle::Decimal,
+            prefix: Some("p. ".into()),
+            start: 1,
+        };
+        data.iter_mut().for_each(|d| d.update_labels(&label));
+        let forms = data[1].children.as_ref().unwrap()[1]
+            .children
+            .as_ref()
+            .unwrap();
+        assert_eq!(forms[0].label.as_deref(), Some("p. 1"));
+    }
+
+    #[test]
+    fn build_category_render_data_test() {
+        let mut db = db();
+        db.apply_categories(&HashMap::from([("Vital Signs".into(), "Safety".into())]));
+        let builder = ACrfBuilder::new(db);
+        let data = builder.build_render_data(&TocLayout::default(), &TocLocale::default());
+        // V1 -> [Demographics, Safety -> [Vital Signs]]
+        let v1 = &data[0].children.as_ref().unwrap()[0];
+        let v1_children = v1.children.as_ref().unwrap();
+        assert_eq!(v1_children[0].name, "Demographics");
+        assert_eq!(v1_children[1].name, "Safety");
+        let safety = v1_children[1].children.as_ref().unwrap();
+        assert_eq!(safety[0].id, Some(0));
+        assert!(matches!(safety[0].kind, Level::LEVEL4));
+        // Forms -> [Demographics, Safety -> [Vital Signs -> [V1, V2]]]
+        let forms = data[1].children.as_ref().unwrap();
+        assert_eq!(forms[1].name, "Safety");
+        let vital_signs = &forms[1].children.as_ref().unwrap()[0];
+        assert_eq!(vital_signs.children.as_ref().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn build_hidden_render_data_test() {
+        let mut db = db();
+        db.form[1].hidden = true;
+        db.visit[1].hidden = true;
+        let builder = ACrfBuilder::new(db);
+        let data = builder.build_render_data(&TocLayout::default(), &TocLocale::default());
+        // V1 -> [Vital Signs]
+        let visits = data[0].children.as_ref().unwrap();
+        assert_eq!(visits.len(), 1);
+        let v1_children = visits[0].children.as_ref().unwrap();
+        assert_eq!(v1_children.len(), 1);
+        assert_eq!(v1_children[0].id, Some(0));
+        // Forms -> [Vital Signs -> [V1]]
+        let forms = data[1].children.as_ref().unwrap();
+        assert_eq!(forms.len(), 1);
+        assert_eq!(forms[0].children.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn build_domain_render_data_test() {
+        let annotation = |page: usize, domain: Option<&str>| Annotation {
+            form: None,
+            page,
+            text: String::new(),
+            domain: domain.map(String::from),
+            variable: None,
+            rect: [0.0; 4],
+        };
+        let mut builder = ACrfBuilder::new(db());
+        builder.annotations = vec![
+            annotation(3, Some("VS")),
+            annotation(4, Some("VS")),
+            annotation(4, Some("VS")),
+            annotation(1, Some("DM")),
+            annotation(2, None),
+        ];
+        let mut layout = TocLayout::default();
+        layout
+            .sections
+            .push(TocSection::untitled(SectionKind::Domain));
+        let data = builder.build_render_data(&layout, &TocLocale::default());
+        assert_eq!(data.len(), 3);
+        assert_eq!(data[2].name, "Domains");
+        // Domains -> [DM -> [Demographics], VS -> [Vital Signs, Vital Signs (2)]]
+        let domains = data[2].children.as_ref().unwrap();
+        assert_eq!(domains[0].name, "DM");
+        let vs = domains[1].children.as_ref().unwrap();
+        assert_eq!(vs.len(), 2);
+        assert_eq!(vs[1].name, "Vital Signs (2)");
+        assert_eq!(vs[1].id, Some(0));
+        assert_eq!(vs[1].page, Some(4));
+    }
+
+    #[test]
+    fn build_empty_toc_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        create_dir_all(&root)?;
+        let source = root.join("ecrf.pdf");
+        blank_ecrf(&[&[], &[], &[]]).save(&source)?;
+        // domain section is left out as source has no annotations
+        let mut builder = ACrfBuilder::new(db());
+        let result = builder.build(BuildParam {
+            source: source.clone(),
+            destination: root.join("acrf.pdf"),
+            workspace: root.clone(),
+            bookmark_bin: root.join("bookmark.exe"),
+            options: BuildOptions {
+                layout: TocLayout {
+                    sections: vec![TocSection::domain("Domains")],
+                    categories: false,
+                },
+                ..Default::default()
+            },
+        });
+        assert!(result.unwrap_err().to_string().starts_with("toc is empty"));
+        assert!(!root.join("toc.pdf").exists());
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn merge_binding_test() {
+        let mut db = db();
+        // Vital Signs is bound twice, V2 is in both bindings
+        db.visit.push(visit(2, "V3"));
+        db.binding.push(bind(0, &[1, 2]));
+        let builder = ACrfBuilder::new(db);
+        assert_eq!(builder.form_visit_binding[&0], vec![0, 1, 2]);
+        assert_eq!(builder.visit_form_binding[&1], vec![0]);
+        let data = builder.build_render_data(&TocLayout::forms_only(), &TocLocale::default());
+        let vital_signs = data[0]
+            .children
+            .as_ref()
+            .unwrap()
+            .iter()
+            .find(|f| f.name.eq("Vital Signs"))
+            .unwrap();
+        assert_eq!(vital_signs.children.as_ref().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,470 @@
+use lopdf::{Dictionary, Document, Object};
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt::Display,
+    fs,
+    path::Path,
+};
+
+/// technical rules for pdf of annotated crf, following the study data technical conformance guide
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// bookmarks panel opens with the document, `/PageMode /UseOutlines`
+    PageMode,
+    /// destinations of bookmarks and links keep the zoom of reader
+    InheritedZoom,
+    /// bookmarks and links point to pages of the document
+    Links,
+    EmbeddedFonts,
+    /// pdf version from 1.4 to 1.7
+    Version,
+    /// no encryption or password
+    Security,
+    FileSize,
+    BookmarkDepth,
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Rule::PageMode => "Page mode",
+            Rule::InheritedZoom => "Inherited zoom",
+            Rule::Links => "Links",
+            Rule::EmbeddedFonts => "Embedded fonts",
+            Rule::Version => "PDF version",
+            Rule::Security => "Security",
+            Rule::FileSize => "File size",
+            Rule::BookmarkDepth => "Bookmark depth",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    /// largest file size allowed in bytes
+    pub max_size: u64,
+    /// deepest bookmark level allowed, top level bookmarks are of level 1, 2 levels by default
+    /// as submission guidance recommends
+    pub max_bookmark_depth: usize,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        CheckOptions {
+            max_size: 100 * 1024 * 1024,
+            max_bookmark_depth: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RuleResult {
+    pub rule: Rule,
+    pub passed: bool,
+    /// reasons of failure, empty if passed
+    pub messages: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    pub results: Vec<RuleResult>,
+}
+
+impl CheckReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|r| r.passed)
+    }
+
+    pub fn result(&self, rule: Rule) -> Option<&RuleResult> {
+        self.results.iter().find(|r| r.rule.eq(&rule))
+    }
+
+    fn push(&mut self, rule: Rule, messages: Vec<String>) {
+        self.results.push(RuleResult {
+            rule,
+            passed: messages.is_empty(),
+            messages,
+        });
+    }
+}
+
+/// one line for each rule, followed by reasons of failure
+impl Display for CheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for result in self.results.iter() {
+            let status = if result.passed { "PASS" } else { "FAIL" };
+            writeln!(f, "[{}] {}", status, result.rule)?;
+            for message in result.messages.iter() {
+                writeln!(f, "       {}", message)?;
+            }
+        }
+        let failed = self.results.iter().filter(|r| !r.passed).count();
+        write!(
+            f,
+            "{} of {} rules passed",
+            self.results.len() - failed,
+            self.results.len()
+        )
+    }
+}
+
+/// check built pdf against technical rules of submission
+pub fn check<P: AsRef<Path>>(path: P, options: &CheckOptions) -> anyhow::Result<CheckReport> {
+    let size = fs::metadata(path.as_ref())?.len();
+    let doc = Document::load(path.as_ref())?;
+    let mut report = CheckReport::default();
+
+    let mut messages = vec![];
+    let page_mode = doc.catalog()?.get(b"PageMode").and_then(Object::as_name);
+    if !matches!(page_mode, Ok(b"UseOutlines")) {
+        messages.push("document does not open with bookmarks panel".into());
+    }
+    report.push(Rule::PageMode, messages);
+
+    let pages = doc.get_pages().into_values().collect::<HashSet<_>>();
+    let mut zoom = vec![];
+    let mut links = vec![];
+    let (outlines, depth) = outline_items(&doc);
+    let annotations = doc.get_pages().into_iter().flat_map(|(page, id)| {
+        doc.get_page_annotations(id)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|a| matches!(a.get(b"Subtype").and_then(Object::as_name), Ok(b"Link")))
+            .map(move |a| (format!("link on page {}", page), a))
+    });
+    for (name, item) in outlines
+        .iter()
+        .map(|(title, item)| (format!("bookmark \"{}\"", title), *item))
+        .chain(annotations)
+    {
+        let Some(dest) = destination(&doc, item) else {
+            // links to web pages or files have no destination in document
+            if item.has(b"Dest") || is_goto(item) {
+                links.push(format!("{} has no valid destination", name));
+            }
+            continue;
+        };
+        match dest.first() {
+            Some(Object::Reference(id)) if pages.contains(id) => {}
+            Some(Object::Integer(_)) => links.push(format!(
+                "{} points to a page number instead of a page",
+                name
+            )),
+            _ => links.push(format!("{} points to no page of document", name)),
+        }
+        let inherited = match dest.get(1).map(Object::as_name) {
+            Some(Ok(b"XYZ")) => matches!(
+                dest.get(4),
+                None | Some(Object::Null) | Some(Object::Integer(0))
+            ),
+            _ => false,
+        };
+        if !inherited {
+            zoom.push(format!("{} does not inherit zoom", name));
+        }
+    }
+    report.push(Rule::InheritedZoom, zoom);
+    report.push(Rule::Links, links);
+
+    report.push(Rule::EmbeddedFonts, unembedded_fonts(&doc));
+
+    let mut messages = vec![];
+    let version = doc
+        .version
+        .split_once('.')
+        .and_then(|(major, minor)| Some((major.parse::<u32>().ok()?, minor.parse::<u32>().ok()?)));
+    if !matches!(version, Some((1, 4..=7))) {
+        messages.push(format!("version {} is not from 1.4 to 1.7", doc.version));
+    }
+    report.push(Rule::Version, messages);
+
+    let mut messages = vec![];
+    if doc.trailer.has(b"Encrypt") {
+        messages.push("document is encrypted".into());
+    }
+    report.push(Rule::Security, messages);
+
+    let mut messages = vec![];
+    if size > options.max_size {
+        messages.push(format!(
+            "size of {} bytes exceeds limit of {} bytes",
+            size, options.max_size
+        ));
+    }
+    report.push(Rule::FileSize, messages);
+
+    let mut messages = vec![];
+    if depth > options.max_bookmark_depth {
+        messages.push(format!(
+            "bookmarks are nested {} levels deep, at most {} levels are allowed",
+            depth, options.max_bookmark_depth
+        ));
+    }
+    report.push(Rule::BookmarkDepth, messages);
+    Ok(report)
+}
+
+/// title and dictionary of every outline item, and depth of outline tree
+pub(crate) fn outline_items(doc: &Document) -> (Vec<(String, &Dictionary)>, usize) {
+    let mut items = vec![];
+    let mut depth = 0;
+    let first = doc
+        .catalog()
+        .and_then(|c| c.get_deref(b"Outlines", doc))
+        .and_then(Object::as_dict)
+        .and_then(|o| o.get(b"First"))
+        .and_then(Object::as_reference);
+    let mut queue = VecDeque::new();
+    if let Ok(first) = first {
+        queue.push_back((first, 1));
+    }
+    let mut visited = HashSet::new();
+    while let Some((id, level)) = queue.pop_front() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let Ok(item) = doc.get_dictionary(id) else {
+            continue;
+        };
+        depth = depth.max(level);
+        let title = item
+            .get(b"Title")
+            .and_then(lopdf::decode_text_string)
+            .unwrap_or_default();
+        items.push((title, item));
+        if let Ok(next) = item.get(b"Next").and_then(Object::as_reference) {
+            queue.push_back((next, level));
+        }
+        if let Ok(child) = item.get(b"First").and_then(Object::as_reference) {
+            queue.push_back((child, level + 1));
+        }
+    }
+    (items, depth)
+}
+
+fn is_goto(item: &Dictionary) -> bool {
+    matches!(
+        item.get(b"A")
+            .and_then(Object::as_dict)
+            .and_then(|a| a.get(b"S"))
+            .and_then(Object::as_name),
+        Ok(b"GoTo")
+    )
+}
+
+/// explicit destination of bookmark or link, from `/Dest` or a GoTo action,
+/// named destinations are looked up in `/Dests` of catalog and the `/Dests` name tree
+pub(crate) fn destination<'a>(doc: &'a Document, item: &'a Dictionary) -> Option<Vec<Object>> {
+    let dest = match item.get(b"Dest") {
+        Ok(dest) => dest,
+        Err(_) if is_goto(item) => item
+            .get(b"A")
+            .and_then(Object::as_dict)
+            .ok()?
+            .get(b"D")
+            .ok()?,
+        Err(_) => return None,
+    };
+    resolve(doc, dest, 0)
+}
+
+fn resolve(doc: &Document, dest: &Object, nb_deref: usize) -> Option<Vec<Object>> {
+    if nb_deref > 8 {
+        return None;
+    }
+    let (_, dest) = doc.dereference(dest).ok()?;
+    match dest {
+        Object::Array(dest) => Some(dest.clone()),
+        Object::Dictionary(dict) => resolve(doc, dict.get(b"D").ok()?, nb_deref + 1),
+        Object::Name(name) => {
+            let dests = doc.catalog().ok()?.get_deref(b"Dests", doc).ok()?;
+            resolve(doc, dests.as_dict().ok()?.get(name).ok()?, nb_deref + 1)
+        }
+        Object::String(name, _) => {
+            let tree = doc
+                .catalog()
+                .ok()?
+                .get_deref(b"Names", doc)
+                .and_then(Object::as_dict)
+                .ok()?
+                .get_deref(b"Dests", doc)
+                .and_then(Object::as_dict)
+                .ok()?;
+            resolve(doc, lookup_name(doc, tree, name, 0)?, nb_deref + 1)
+        }
+        _ => None,
+    }
+}
+
+/// find value of key in name tree
+pub(crate) fn lookup_name<'a>(
+    doc: &'a Document,
+    node: &'a Dictionary,
+    key: &[u8],
+    level: usize,
+) -> Option<&'a Object> {
+    if level > 32 {
+        return None;
+    }
+    if let Ok(names) = node.get_deref(b"Names", doc).and_then(Object::as_array) {
+        for pair in names.chunks(2) {
+            if let [Object::String(name, _), value] = pair {
+                if name.as_slice().eq(key) {
+                    return Some(value);
+                }
+            }
+        }
+    }
+    let kids = node
+        .get_deref(b"Kids", doc)
+        .and_then(Object::as_array)
+        .ok()?;
+    kids.iter()
+        .filter_map(|kid| doc.dereference(kid).ok()?.1.as_dict().ok())
+        .find_map(|kid| lookup_name(doc, kid, key, level + 1))
+}
+
+/// base names of fonts without font file, type 3 fonts are drawn by the document itself
+fn unembedded_fonts(doc: &Document) -> Vec<String> {
+    let mut fonts = vec![];
+    let mut seen = HashSet::new();
+    for object in doc.objects.values() {
+        let Ok(font) = object.as_dict() else {
+            continue;
+        };
+        if !font.type_is(b"Font") {
+            continue;
+        }
+        // descendants of type 0 fonts are checked with their parent
+        let subtype = font
+            .get(b"Subtype")
+            .and_then(Object::as_name)
+            .unwrap_or(b"");
+        if matches!(subtype, b"Type3" | b"CIDFontType0" | b"CIDFontType2") {
+            continue;
+        }
+        let descriptor_font = if subtype.eq(b"Type0") {
+            font.get_deref(b"DescendantFonts", doc)
+                .and_then(Object::as_array)
+                .ok()
+                .and_then(|fonts| fonts.first())
+                .and_then(|f| doc.dereference(f).ok())
+                .and_then(|(_, f)| f.as_dict().ok())
+        } else {
+            Some(font)
+        };
+        let embedded = descriptor_font
+            .and_then(|f| f.get_deref(b"FontDescriptor", doc).ok())
+            .and_then(|d| d.as_dict().ok())
+            .map(|d| d.has(b"FontFile") || d.has(b"FontFile2") || d.has(b"FontFile3"))
+            .unwrap_or(false);
+        if !embedded {
+            let name = font
+                .get(b"BaseFont")
+                .and_then(Object::as_name_str)
+                .unwrap_or("unnamed")
+                .to_string();
+            if seen.insert(name.clone()) {
+                fonts.push(format!("font {} is not embedded", name));
+            }
+        }
+    }
+    fonts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acrf::annotate::tests::blank_ecrf;
+    use lopdf::{dictionary, text_string};
+    use nanoid::nanoid;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    #[test]
+    fn check_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        create_dir_all(&root)?;
+        let path = root.join("acrf.pdf");
+        let mut doc = blank_ecrf(&[&[(700, "Demographics")], &[(700, "Vital Signs")]]);
+        let pages = doc.get_pages();
+        let xyz = |page: Object| vec![page, "XYZ".into(), Object::Null, Object::Null, Object::Null];
+        // Forms -> Demographics -> V1
+        let outlines_id = doc.new_object_id();
+        let section_id = doc.new_object_id();
+        let form_id = doc.new_object_id();
+        let visit_id = doc.add_object(dictionary! {
+            "Title" => text_string("V1"),
+            "Parent" => form_id,
+            "Dest" => xyz(pages[&1].into()),
+        });
+        doc.objects.insert(
+            form_id,
+            Object::Dictionary(dictionary! {
+                "Title" => text_string("Demographics"),
+                "Parent" => section_id,
+                "First" => visit_id,
+                "Last" => visit_id,
+                "Dest" => vec![pages[&1].into(), "Fit".into()],
+            }),
+        );
+        doc.objects.insert(
+            section_id,
+            Object::Dictionary(dictionary! {
+                "Title" => text_string("Forms"),
+                "Parent" => outlines_id,
+                "First" => form_id,
+                "Last" => form_id,
+            }),
+        );
+        doc.objects.insert(
+            outlines_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => section_id,
+                "Last" => section_id,
+            }),
+        );
+        let link_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+            "Dest" => xyz(1.into()),
+        });
+        doc.get_dictionary_mut(pages[&1])?
+            .set("Annots", vec![link_id.into()]);
+        let catalog = doc.catalog_mut()?;
+        catalog.set("Outlines", outlines_id);
+        catalog.set("PageMode", "UseOutlines");
+        doc.save(&path)?;
+
+        let report = check(&path, &CheckOptions::default())?;
+        let passed = |rule| report.result(rule).unwrap().passed;
+        assert!(passed(Rule::PageMode));
+        assert!(passed(Rule::Version));
+        assert!(passed(Rule::Security));
+        assert!(passed(Rule::FileSize));
+        assert!(!passed(Rule::EmbeddedFonts));
+        let links = report.result(Rule::Links).unwrap();
+        assert_eq!(links.messages.len(), 1);
+        assert!(links.messages[0].starts_with("link on page 1"));
+        let zoom = report.result(Rule::InheritedZoom).unwrap();
+        assert_eq!(
+            zoom.messages,
+            vec!["bookmark \"Demographics\" does not inherit zoom"]
+        );
+        assert!(!passed(Rule::BookmarkDepth));
+        assert!(!report.passed());
+        assert!(report.to_string().ends_with("4 of 8 rules passed"));
+
+        let options = CheckOptions {
+            max_size: 1,
+            max_bookmark_depth: 3,
+        };
+        let report = check(&path, &options)?;
+        assert!(!report.result(Rule::FileSize).unwrap().passed);
+        assert!(report.result(Rule::BookmarkDepth).unwrap().passed);
+        remove_dir_all(root)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+use lopdf::{dictionary, Dictionary, Document, Object};
+
+/// numbering style of page labels
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LabelStyle {
+    #[default]
+    Decimal,
+    UpperRoman,
+    LowerRoman,
+    UpperLetter,
+    LowerLetter,
+}
+
+/// label of pages of one part of acrf, such as `i, ii, iii` for toc or `1, 2, 3` for crf pages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageLabel {
+    pub style: LabelStyle,
+    /// text before number, such as `CRF-`
+    pub prefix: Option<String>,
+    /// number of the first page of part
+    pub start: usize,
+}
+
+/// page labels of toc and crf body, parts without label are numbered by viewers as usual
+#[derive(Debug, Clone)]
+pub struct PageLabels {
+    pub toc: Option<PageLabel>,
+    pub body: Option<PageLabel>,
+}
+
+impl Default for PageLabels {
+    fn default() -> Self {
+        PageLabels {
+            toc: Some(PageLabel::new(LabelStyle::LowerRoman)),
+            body: Some(PageLabel::new(LabelStyle::Decimal)),
+        }
+    }
+}
+
+impl PageLabel {
+    pub fn new(style: LabelStyle) -> Self {
+        PageLabel {
+            style,
+            prefix: None,
+            start: 1,
+        }
+    }
+
+    /// label of the page at zero based index in part
+    pub fn format(&self, index: usize) -> String {
+        let number = self.start + index;
+        let number = match self.style {
+            LabelStyle::Decimal => number.to_string(),
+            LabelStyle::UpperRoman => roman(number),
+            LabelStyle::LowerRoman => roman(number).to_lowercase(),
+            LabelStyle::UpperLetter => letter(number),
+            LabelStyle::LowerLetter => letter(number).to_lowercase(),
+        };
+        format!("{}{}", self.prefix.as_deref().unwrap_or_default(), number)
+    }
+
+    fn to_dictionary(&self) -> Dictionary {
+        let style = match self.style {
+            LabelStyle::Decimal => "D",
+            LabelStyle::UpperRoman => "R",
+            LabelStyle::LowerRoman => "r",
+            LabelStyle::UpperLetter => "A",
+            LabelStyle::LowerLetter => "a",
+        };
+        let mut dict = dictionary! { "S" => style };
+        if let Some(prefix) = self.prefix.as_deref() {
+            dict.set("P", lopdf::text_string(prefix));
+        }
+        if self.start != 1 {
+            dict.set("St", self.start as i64);
+        }
+        dict
+    }
+}
+
+fn roman(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut text = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            text.push_str(numeral);
+            number -= value;
+        }
+    }
+    text
+}
+
+/// A to Z, then AA to ZZ and so on
+fn letter(number: usize) -> String {
+    if number == 0 {
+        return String::new();
+    }
+    let c = (b'A' + ((number - 1) % 26) as u8) as char;
+    c.to_string().repeat((number - 1) / 26 + 1)
+}
+
+/// write `/PageLabels` number tree into catalog, toc takes the first `toc_pages` pages
+pub(crate) fn write_page_labels(doc: &mut Document, toc_pages: usize, labels: &PageLabels) {
+    let Ok(catalog) = doc.catalog_mut() else {
+        return;
+    };
+    if labels.toc.is_none() && labels.body.is_none() {
+        catalog.remove(b"PageLabels");
+        return;
+    }
+    let mut parts = vec![(toc_pages, labels.body.as_ref())];
+    if toc_pages > 0 {
+        parts.insert(0, (0, labels.toc.as_ref()));
+    }
+    let mut nums = vec![];
+    for (index, label) in parts {
+        let label = match label {
+            Some(label) => label.to_dictionary(),
+            // part without label keeps the numbers viewers show by default
+            None => dictionary! { "S" => "D", "St" => (index + 1) as i64 },
+        };
+        nums.push(Object::Integer(index as i64));
+        nums.push(Object::Dictionary(label));
+    }
+    catalog.set("PageLabels", dictionary! { "Nums" => nums });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_test() {
+        let label = PageLabel::new(LabelStyle::LowerRoman);
+        assert_eq!(label.format(0), "i");
+        assert_eq!(label.format(3), "iv");
+        assert_eq!(
+            PageLabel::new(LabelStyle::UpperRoman).format(1993),
+            "MCMXCIV"
+        );
+        assert_eq!(PageLabel::new(LabelStyle::UpperLetter).format(27), "BB");
+        let label = PageLabel {
+            style: LabelStyle::Decimal,
+            prefix: Some("CRF-".into()),
+            start: 5,
+        };
+        assert_eq!(label.format(0), "CRF-5");
+    }
+
+    #[test]
+    fn write_page_labels_test() -> anyhow::Result<()> {
+        let mut doc = Document::with_version("1.7");
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog" });
+        doc.trailer.set("Root", catalog_id);
+        write_page_labels(&mut doc, 2, &PageLabels::default());
+        let nums = doc
+            .catalog()?
+            .get(b"PageLabels")?
+            .as_dict()?
+            .get(b"Nums")?
+            .as_array()?;
+        assert_eq!(nums.len(), 4);
+        assert_eq!(nums[2].as_i64()?, 2);
+        assert_eq!(nums[1].as_dict()?.get(b"S")?.as_name()?, b"r");
+        assert_eq!(nums[3].as_dict()?.get(b"S")?.as_name()?, b"D");
+
+        let labels = PageLabels {
+            toc: None,
+            body: Some(PageLabel::new(LabelStyle::Decimal)),
+        };
+        write_page_labels(&mut doc, 2, &labels);
+        let nums = doc
+            .catalog()?
+            .get(b"PageLabels")?
+            .as_dict()?
+            .get(b"Nums")?
+            .as_array()?;
+        assert_eq!(nums.len(), 4);
+        assert_eq!(nums[1].as_dict()?.get(b"St")?.as_i64()?, 1);
+        assert!(nums[3].as_dict()?.get(b"St").is_err());
+        Ok(())
+    }
+}
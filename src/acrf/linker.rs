@@ -1,61 +1,310 @@
-use super::builder::{ACrfBuilder, LinkBookmarkParam};
+use super::{
+    builder::{ACrfBuilder, LinkBookmarkParam},
+    check::{destination, outline_items},
+};
 use anyhow::anyhow;
-use lopdf::{dictionary, Document, Object, ObjectId};
-use std::{fs, ops::Add, os::windows::process::CommandExt, path::Path, process::Command};
+use lopdf::{dictionary, Dictionary, Document, Object};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    os::windows::process::CommandExt,
+    path::Path,
+    process::Command,
+};
+
+/// link or bookmark which does not land on the page of its form
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkIssue {
+    /// where the link is, such as `toc link on page 2` or `bookmark "V1"`
+    pub source: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LinkReport {
+    /// count of toc links and bookmarks verified
+    pub checked: usize,
+    pub issues: Vec<LinkIssue>,
+}
+
+impl LinkReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// toc link rewritten by `link_toc`, identified by its page and rectangle
+/// since object ids are not kept when bookmarks are added
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TocLink {
+    page: u32,
+    rect: [i64; 4],
+    /// page number the link should land on
+    target: u32,
+}
 
 impl ACrfBuilder {
-    pub fn link_toc<P: AsRef<Path>>(&self, target: P) -> anyhow::Result<()> {
+    /// point toc links named by form id and page to named destination of the page, such as
+    /// `18_46` to `form_18_2` if form 18 starts at page 45 of crf, return links written and
+    /// links which could not be resolved
+    pub(crate) fn link_toc<P: AsRef<Path>>(
+        &self,
+        target: P,
+    ) -> anyhow::Result<(Vec<TocLink>, Vec<LinkIssue>)> {
+        let mut document = Document::load(target.as_ref())?;
+        let pages = document.get_pages();
+        let names = self.form_destinations(pages.len());
+        let mut links = vec![];
+        let mut issues = vec![];
+        for page in 1..=self.toc_pages.get() as u32 {
+            let Some(page_id) = pages.get(&page) else {
+                break;
+            };
+            // toc pages printed by browser refer to their annotations
+            let annotations = document
+                .get_dictionary(*page_id)?
+                .get_deref(b"Annots", &document)
+                .and_then(Object::as_array)
+                .map(|annots| {
+                    annots
+                        .iter()
+                        .filter_map(|a| a.as_reference().ok())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            for id in annotations {
+                let annotation = document.get_dictionary(id)?;
+                let Ok(name) = annotation.get(b"Dest").and_then(Object::as_name_str) else {
+                    continue;
+                };
+                let source = format!("toc link on page {}", page);
+                // anchors are `<form id>_<page>`, a plain form id links to the first page of form
+                let parsed: Option<(usize, Option<usize>)> = match name.split_once('_') {
+                    Some((id, page)) => id.parse().ok().zip(page.parse().ok().map(Some)),
+                    None => name.parse().ok().map(|id| (id, None)),
+                };
+                let Some((form_id, crf_page)) = parsed else {
+                    issues.push(LinkIssue {
+                        source,
+                        message: format!("destination {} is not a form id", name),
+                    });
+                    continue;
+                };
+                let Some(form) = self.form_map.get(&form_id) else {
+                    issues.push(LinkIssue {
+                        source,
+                        message: format!("form {} is not found", form_id),
+                    });
+                    continue;
+                };
+                let crf_page = crf_page.unwrap_or(form.page);
+                let destination = crf_page
+                    .checked_sub(form.page)
+                    .map(|offset| form_destination(form_id, offset + 1));
+                let Some((destination, target)) = destination
+                    .and_then(|d| names.get(&d).map(|target| (d, *target)))
+                    .filter(|(_, target)| pages.contains_key(target))
+                else {
+                    issues.push(LinkIssue {
+                        source,
+                        message: format!("page {} of form {} is not found", crf_page, form.name),
+                    });
+                    continue;
+                };
+                links.push(TocLink {
+                    page,
+                    rect: rect_key(annotation),
+                    target,
+                });
+                document
+                    .get_dictionary_mut(id)?
+                    .set("Dest", Object::string_literal(destination));
+            }
+        }
+        document.save(target.as_ref())?;
+        Ok((links, issues))
+    }
+
+    /// named destinations of form pages in target, `form_<id>` for the first page of form
+    /// and `form_<id>_<n>` for its n-th page, a form ends before the next form starts
+    pub(crate) fn form_destinations(&self, page_count: usize) -> BTreeMap<String, u32> {
+        let base = self.toc_pages.get();
+        let mut starts = self.form_map.values().map(|f| f.page).collect::<Vec<_>>();
+        starts.sort();
+        starts.dedup();
+        let mut names = BTreeMap::new();
+        for form in self.form_map.values() {
+            let end = starts
+                .iter()
+                .find(|page| **page > form.page)
+                .map(|page| page - 1)
+                .unwrap_or(page_count.saturating_sub(base));
+            for (index, page) in (form.page..=end).enumerate() {
+                names.insert(form_destination(form.id, index + 1), (base + page) as u32);
+            }
+        }
+        names
+    }
+
+    /// write named destinations of forms into `/Names` of catalog and point bookmarks to them,
+    /// so that links stay valid if pages are inserted later
+    pub(crate) fn link_names<P: AsRef<Path>>(&self, target: P) -> anyhow::Result<()> {
         let mut document = Document::load(target.as_ref())?;
-        let obj_ids = document
-            .objects
+        let pages = document.get_pages();
+        let names = self.form_destinations(pages.len());
+
+        // bookmarks on the first page of form use the destination of form
+        let mut page_names: HashMap<u32, &str> = HashMap::new();
+        for (name, page) in names.iter() {
+            let preferred = page_names
+                .get(page)
+                .map(|n| n.len() > name.len())
+                .unwrap_or(true);
+            if preferred {
+                page_names.insert(*page, name);
+            }
+        }
+        let page_numbers = pages
             .iter()
-            .map(|(id, _)| id.clone())
-            .collect::<Vec<ObjectId>>();
-        for id in obj_ids {
-            let obj = document.get_object_mut(id)?;
-            if let Ok(obj) = obj.as_dict_mut() {
-                if obj.type_is(b"Annot") {
-                    if let Ok(dest) = obj.get(b"Dest") {
-                        if let Ok(dest) = dest.as_name_str() {
-                            let id = dest.to_string().parse::<usize>()?;
-                            if let Some(f) = self.form_map.get(&id) {
-                                obj.set(
-                                    b"Dest",
-                                    Object::Array(vec![
-                                        Object::Integer(self.toc_pages.get().add(f.page - 1) as i64),
-                                        Object::Name(b"XYZ".into()),
-                                        Object::Null,
-                                        Object::Null,
-                                        Object::Null,
-                                        Object::Dictionary(dictionary! {
-                                            "XYZ" => vec![Object::Null, Object::Null, Object::Null]
-                                        }),
-                                    ]),
-                                );
-                            }
-                        }
-                    }
-                }
+            .map(|(number, id)| (*id, *number))
+            .collect::<HashMap<_, _>>();
+        let mut rewrites = vec![];
+        for (id, object) in document.objects.iter() {
+            let Ok(item) = object.as_dict() else {
+                continue;
+            };
+            if !item.has(b"Title") || !item.has(b"Parent") {
+                continue;
+            }
+            // bookmark binary may write page index instead of page
+            let page = match destination(&document, item).as_deref() {
+                Some([Object::Reference(page), ..]) => page_numbers.get(page).copied(),
+                Some([Object::Integer(index), ..]) => Some(*index as u32 + 1),
+                _ => None,
+            };
+            if let Some(name) = page.and_then(|p| page_names.get(&p)) {
+                rewrites.push((*id, name.to_string()));
+            }
+        }
+        for (id, name) in rewrites {
+            let item = document.get_dictionary_mut(id)?;
+            item.remove(b"A");
+            item.set("Dest", Object::string_literal(name));
+        }
+
+        let tree = names
+            .iter()
+            .filter_map(|(name, page)| Some((name, *pages.get(page)?)))
+            .flat_map(|(name, page_id)| {
+                [
+                    Object::string_literal(name.as_str()),
+                    Object::Array(vec![
+                        Object::Reference(page_id),
+                        "XYZ".into(),
+                        Object::Null,
+                        Object::Null,
+                        Object::Null,
+                    ]),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree_id = document.add_object(dictionary! { "Names" => tree });
+        let catalog_names = document.catalog()?.get(b"Names").cloned();
+        match catalog_names {
+            Ok(Object::Reference(id)) => document.get_dictionary_mut(id)?.set("Dests", tree_id),
+            Ok(Object::Dictionary(mut dict)) => {
+                dict.set("Dests", tree_id);
+                document.catalog_mut()?.set("Names", dict);
             }
+            _ => document
+                .catalog_mut()?
+                .set("Names", dictionary! { "Dests" => tree_id }),
         }
         document.save(target.as_ref())?;
         Ok(())
     }
 
-    pub fn link_bookmark<P: AsRef<Path>>(&self, param: LinkBookmarkParam<P>) -> anyhow::Result<()> {
-        let mut render_data = vec![];
-        let base = self.toc_pages.get();
+    /// resolve toc links and bookmarks of built pdf to pages, and check that they land on
+    /// the pages of their entries, toc links are checked against the page in their anchor,
+    /// bookmarks are matched to render data by title
+    pub(crate) fn verify_links<P: AsRef<Path>>(
+        &self,
+        target: P,
+        links: &[TocLink],
+    ) -> anyhow::Result<LinkReport> {
+        let document = Document::load(target.as_ref())?;
+        let pages = document.get_pages();
+        let page_numbers = pages
+            .iter()
+            .map(|(number, id)| (*id, *number))
+            .collect::<HashMap<_, _>>();
+        let resolve = |item: &Dictionary| match destination(&document, item)?.first() {
+            Some(Object::Reference(id)) => page_numbers.get(id).copied(),
+            _ => None,
+        };
+        let mut report = LinkReport::default();
+
+        for link in links {
+            report.checked += 1;
+            let source = format!("toc link on page {}", link.page);
+            let annotation = pages
+                .get(&link.page)
+                .and_then(|id| document.get_page_annotations(*id).ok())
+                .and_then(|annots| annots.into_iter().find(|a| rect_key(a).eq(&link.rect)));
+            let message = match annotation.map(resolve) {
+                None => "link is missing".to_string(),
+                Some(None) => "destination is not a page of document".to_string(),
+                Some(Some(page)) if page.ne(&link.target) => {
+                    format!("lands on page {} instead of page {}", page, link.target)
+                }
+                Some(Some(_)) => continue,
+            };
+            report.issues.push(LinkIssue { source, message });
+        }
 
-        if let Some(data) = self.visit_render_data.as_ref() {
-            let mut data = data.clone();
-            data.update_pages(base);
-            render_data.push(data);
+        let base = self.toc_pages.get();
+        let mut expected: HashMap<&str, Vec<u32>> = HashMap::new();
+        let mut stack = self.render_data.iter().collect::<Vec<_>>();
+        while let Some(data) = stack.pop() {
+            if let Some(page) = data.page {
+                expected
+                    .entry(&data.name)
+                    .or_default()
+                    .push((base + page) as u32);
+            }
+            stack.extend(data.children.iter().flatten());
         }
-        if let Some(data) = self.form_render_data.as_ref() {
-            let mut data = data.clone();
-            data.update_pages(base);
-            render_data.push(data);
+        let (items, _) = outline_items(&document);
+        for (title, item) in items {
+            // sections and groups without destination only hold their children
+            if item.has(b"First") && !item.has(b"Dest") && !item.has(b"A") {
+                continue;
+            }
+            report.checked += 1;
+            let source = format!("bookmark \"{}\"", title);
+            let message = match (resolve(item), expected.get(title.as_str())) {
+                (None, _) => "destination is not a page of document".to_string(),
+                (Some(page), Some(pages)) if !pages.contains(&page) => {
+                    format!("lands on page {} which is not a page of {}", page, title)
+                }
+                _ => continue,
+            };
+            report.issues.push(LinkIssue { source, message });
         }
+        Ok(report)
+    }
+
+    pub fn link_bookmark<P: AsRef<Path>>(&self, param: LinkBookmarkParam<P>) -> anyhow::Result<()> {
+        let base = self.toc_pages.get();
+        let render_data = self
+            .render_data
+            .iter()
+            .map(|data| {
+                let mut data = data.clone();
+                data.update_pages(base);
+                data
+            })
+            .collect::<Vec<_>>();
         let render_file = param.workspace.as_ref().join("bookmark.json");
         fs::write(&render_file, serde_json::to_string(&render_data)?)?;
 
@@ -77,3 +326,196 @@ impl ACrfBuilder {
         Ok(())
     }
 }
+
+fn form_destination(id: usize, page: usize) -> String {
+    match page {
+        1 => format!("form_{}", id),
+        page => format!("form_{}_{}", id, page),
+    }
+}
+
+/// rectangle of annotation rounded to tenths of point, used to find it again after rewrites
+fn rect_key(annotation: &Dictionary) -> [i64; 4] {
+    let mut key = [0; 4];
+    if let Ok(rect) = annotation.get(b"Rect").and_then(Object::as_array) {
+        for (k, v) in key.iter_mut().zip(rect) {
+            *k = (v.as_float().unwrap_or_default() * 10.0).round() as i64;
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        acrf::{
+            annotate::{extract::Annotation, tests::blank_ecrf},
+            toc::{
+                layout::{TocLayout, TocSection},
+                locale::TocLocale,
+            },
+        },
+        edc::db::tests::db,
+    };
+    use lopdf::{dictionary, text_string, ObjectId};
+    use nanoid::nanoid;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    /// link on first toc page to destination named `dest`
+    fn toc_link(doc: &mut Document, dest: &str, y: i64) -> Object {
+        Object::Reference(doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![50.into(), y.into(), 200.into(), (y + 12).into()],
+            "Dest" => Object::Name(dest.as_bytes().to_vec()),
+        }))
+    }
+
+    /// outline of a single bookmark pointing to page
+    fn outline(doc: &mut Document, title: &str, page: ObjectId) -> anyhow::Result<ObjectId> {
+        let outlines_id = doc.new_object_id();
+        let item_id = doc.add_object(dictionary! {
+            "Title" => text_string(title),
+            "Parent" => outlines_id,
+            "Dest" => vec![page.into(), "XYZ".into(), Object::Null, Object::Null, Object::Null],
+        });
+        doc.objects.insert(
+            outlines_id,
+            Object::Dictionary(dictionary! { "First" => item_id, "Last" => item_id }),
+        );
+        doc.catalog_mut()?.set("Outlines", outlines_id);
+        Ok(item_id)
+    }
+
+    #[test]
+    fn link_toc_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        create_dir_all(&root)?;
+        let target = root.join("acrf.pdf");
+        // one toc page followed by three pages of forms, Vital Signs starts at page 3 of source
+        let mut doc = blank_ecrf(&[&[(700, "TOC")], &[], &[], &[]]);
+        let pages = doc.get_pages();
+        let annots = ["0", "1_2", "x", "99", "1_3"]
+            .iter()
+            .enumerate()
+            .map(|(i, dest)| toc_link(&mut doc, dest, 700 - 20 * i as i64))
+            .collect::<Vec<_>>();
+        doc.get_dictionary_mut(pages[&1])?.set("Annots", annots);
+        let item_id = outline(&mut doc, "V2", pages[&3])?;
+        doc.save(&target)?;
+
+        let mut builder = ACrfBuilder::new(db());
+        builder.toc_pages.set(1);
+        builder.render_data =
+            builder.build_render_data(&TocLayout::forms_only(), &TocLocale::default());
+        let (links, issues) = builder.link_toc(&target)?;
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[1].target, 3);
+        assert_eq!(issues.len(), 3);
+        assert_eq!(issues[0].message, "destination x is not a form id");
+        assert_eq!(issues[1].message, "form 99 is not found");
+        assert_eq!(
+            issues[2].message,
+            "page 3 of form Demographics is not found"
+        );
+
+        // Demographics covers pages 2 and 3 of target, Vital Signs starts at page 4
+        let names = builder.form_destinations(4);
+        assert_eq!(names.get("form_1"), Some(&2));
+        assert_eq!(names.get("form_1_2"), Some(&3));
+        assert_eq!(names.get("form_0"), Some(&4));
+        builder.link_names(&target)?;
+
+        // V2 is only bound to Vital Signs, but its bookmark lands on page 3
+        let report = builder.verify_links(&target, &links)?;
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].source, "bookmark \"V2\"");
+
+        let mut doc = Document::load(&target)?;
+        let pages = doc.get_pages();
+        let annots = doc.get_page_annotations(pages[&1])?;
+        assert_eq!(annots[0].get(b"Dest")?, &Object::string_literal("form_0"));
+        assert_eq!(annots[1].get(b"Dest")?, &Object::string_literal("form_1_2"));
+        let item = doc.get_dictionary_mut(item_id)?;
+        assert_eq!(item.get(b"Dest")?, &Object::string_literal("form_1_2"));
+        item.set("Dest", Object::string_literal("form_0"));
+        doc.save(&target)?;
+        assert!(builder.verify_links(&target, &links)?.is_ok());
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn link_domain_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        create_dir_all(&root)?;
+        let target = root.join("acrf.pdf");
+        // Demographics covers pages 1 and 2 of source, DM is annotated on its second page
+        let mut builder = ACrfBuilder::new(db());
+        builder.toc_pages.set(1);
+        builder.annotations = vec![Annotation {
+            form: None,
+            page: 2,
+            text: String::new(),
+            domain: Some("DM".into()),
+            variable: None,
+            rect: [0.0; 4],
+        }];
+        let layout = TocLayout {
+            sections: vec![TocSection::domain("Domains")],
+            categories: true,
+        };
+        builder.render_data = builder.build_render_data(&layout, &TocLocale::default());
+        let domains = builder
+            .render_data
+            .last()
+            .unwrap()
+            .children
+            .as_ref()
+            .unwrap();
+        let entry = &domains[0].children.as_ref().unwrap()[0];
+        assert_eq!(entry.name, "Demographics (2)");
+
+        // anchor of entry as written by toc template, bookmark lands on first page of form
+        let mut doc = blank_ecrf(&[&[(700, "TOC")], &[], &[], &[]]);
+        let pages = doc.get_pages();
+        let anchor = format!("{}_{}", entry.id.unwrap(), entry.page.unwrap());
+        let link = toc_link(&mut doc, &anchor, 700);
+        doc.get_dictionary_mut(pages[&1])?.set("Annots", vec![link]);
+        let item_id = outline(&mut doc, &entry.name, pages[&2])?;
+        doc.save(&target)?;
+
+        let (links, issues) = builder.link_toc(&target)?;
+        assert!(issues.is_empty());
+        assert_eq!(links[0].target, 3);
+        builder.link_names(&target)?;
+        let report = builder.verify_links(&target, &links)?;
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].source, "bookmark \"Demographics (2)\"");
+        assert_eq!(
+            report.issues[0].message,
+            "lands on page 2 which is not a page of Demographics (2)"
+        );
+
+        let mut doc = Document::load(&target)?;
+        let annots = doc.get_page_annotations(pages[&1])?;
+        assert_eq!(annots[0].get(b"Dest")?, &Object::string_literal("form_1_2"));
+        let item = doc.get_dictionary_mut(item_id)?;
+        item.set(
+            "Dest",
+            vec![
+                pages[&3].into(),
+                "XYZ".into(),
+                Object::Null,
+                Object::Null,
+                Object::Null,
+            ],
+        );
+        doc.save(&target)?;
+        assert!(builder.verify_links(&target, &links)?.is_ok());
+        remove_dir_all(root)?;
+        Ok(())
+    }
+}
@@ -1,2 +1,4 @@
+pub mod layout;
+pub mod locale;
 pub(crate) mod render;
-mod template;
+pub mod template;
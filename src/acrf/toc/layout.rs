@@ -0,0 +1,96 @@
+/// sections emitted into toc pages and bookmarks, in the listed order
+#[derive(Debug, Clone)]
+pub struct TocLayout {
+    pub sections: Vec<TocSection>,
+    /// insert category level between section and forms if forms have category
+    pub categories: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct TocSection {
+    /// title of section, use the title of toc locale if not specified
+    pub title: Option<String>,
+    pub kind: SectionKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum SectionKind {
+    /// visit -> forms bound to the visit
+    Visit,
+    /// form -> visits the form bound to
+    Form,
+    /// custom group -> forms, such as sdtm domain or form category
+    Group(Vec<FormGroup>),
+    /// sdtm domain -> pages annotated with the domain, domains are scanned from annotations of source,
+    /// the section is left out if source has no annotations, not included in default layout
+    Domain,
+}
+
+/// named group of forms, forms are identified by form id
+#[derive(Debug, Clone)]
+pub struct FormGroup {
+    pub name: String,
+    pub forms: Vec<usize>,
+}
+
+impl Default for TocLayout {
+    fn default() -> Self {
+        TocLayout {
+            sections: vec![
+                TocSection::untitled(SectionKind::Visit),
+                TocSection::untitled(SectionKind::Form),
+            ],
+            categories: true,
+        }
+    }
+}
+
+impl TocLayout {
+    pub fn visits_only() -> Self {
+        TocLayout {
+            sections: vec![TocSection::untitled(SectionKind::Visit)],
+            categories: true,
+        }
+    }
+
+    pub fn forms_only() -> Self {
+        TocLayout {
+            sections: vec![TocSection::untitled(SectionKind::Form)],
+            categories: true,
+        }
+    }
+}
+
+impl TocSection {
+    pub fn untitled(kind: SectionKind) -> Self {
+        TocSection { title: None, kind }
+    }
+
+    pub fn visit(title: &str) -> Self {
+        TocSection {
+            title: Some(title.into()),
+            kind: SectionKind::Visit,
+        }
+    }
+
+    pub fn form(title: &str) -> Self {
+        TocSection {
+            title: Some(title.into()),
+            kind: SectionKind::Form,
+        }
+    }
+
+    pub fn group(title: &str, groups: Vec<FormGroup>) -> Self {
+        TocSection {
+            title: Some(title.into()),
+            kind: SectionKind::Group(groups),
+        }
+    }
+
+    pub fn domain(title: &str) -> Self {
+        TocSection {
+            title: Some(title.into()),
+            kind: SectionKind::Domain,
+        }
+    }
+}
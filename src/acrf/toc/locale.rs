@@ -0,0 +1,95 @@
+use super::layout::SectionKind;
+
+/// controls the language of section titles and how form/visit names are shown
+#[derive(Debug, Clone)]
+pub struct TocLocale {
+    pub language: Language,
+    pub names: NameDisplay,
+    /// separator between original and translated names, such as `访视日期 / Visit Date`
+    pub separator: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum Language {
+    #[default]
+    English,
+    Chinese,
+    Bilingual,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum NameDisplay {
+    /// names read from edc
+    #[default]
+    Original,
+    /// translated names, fallback to original names if translation is missing
+    Translated,
+    /// original and translated names joined by separator
+    Both,
+}
+
+impl Default for TocLocale {
+    fn default() -> Self {
+        TocLocale {
+            language: Language::default(),
+            names: NameDisplay::default(),
+            separator: " / ".into(),
+        }
+    }
+}
+
+impl TocLocale {
+    /// default title of section if layout does not specify one
+    pub fn section_title(&self, kind: &SectionKind) -> String {
+        let (chinese, english) = match kind {
+            SectionKind::Visit => ("访视", "Visit"),
+            SectionKind::Form => ("表单", "Forms"),
+            SectionKind::Group(_) => ("分组", "Groups"),
+            SectionKind::Domain => ("域", "Domains"),
+        };
+        match self.language {
+            Language::English => english.into(),
+            Language::Chinese => chinese.into(),
+            Language::Bilingual => format!("{}{}{}", chinese, self.separator, english),
+        }
+    }
+
+    pub fn display_name(&self, name: &str, translation: Option<&str>) -> String {
+        let translation = translation.filter(|t| !t.trim().is_empty() && !t.eq(&name));
+        match (&self.names, translation) {
+            (NameDisplay::Translated, Some(translation)) => translation.into(),
+            (NameDisplay::Both, Some(translation)) => {
+                format!("{}{}{}", name, self.separator, translation)
+            }
+            _ => name.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_name_test() {
+        let mut locale = TocLocale::default();
+        assert_eq!(
+            locale.display_name("访视日期", Some("Visit Date")),
+            "访视日期"
+        );
+        locale.names = NameDisplay::Translated;
+        assert_eq!(
+            locale.display_name("访视日期", Some("Visit Date")),
+            "Visit Date"
+        );
+        assert_eq!(locale.display_name("死亡", None), "死亡");
+        locale.names = NameDisplay::Both;
+        assert_eq!(
+            locale.display_name("访视日期", Some("Visit Date")),
+            "访视日期 / Visit Date"
+        );
+        assert_eq!(locale.display_name("死亡", Some("")), "死亡");
+        locale.language = Language::Bilingual;
+        assert_eq!(locale.section_title(&SectionKind::Visit), "访视 / Visit");
+    }
+}
@@ -1,4 +1,5 @@
-use super::template::TEMPLATE;
+use super::template::{validate_template, TocStyle, TocTemplate};
+use crate::acrf::label::PageLabel;
 use headless_chrome::{types::PrintToPdfOptions, Browser};
 use serde::Serialize;
 use std::{
@@ -8,22 +9,38 @@ use std::{
 };
 use tera::{Context, Tera};
 
-const TOC_TEMPLATE: &str = "toc";
+const TOC_TEMPLATE: &str = "toc.html";
 
 pub struct Render {
     template: Tera,
+    style: TocStyle,
 }
 
 impl Render {
-    pub fn new() -> anyhow::Result<Self> {
+    pub fn new(toc: &TocTemplate) -> anyhow::Result<Self> {
+        toc.style.validate()?;
         let mut template = Tera::default();
-        template.add_raw_template(TOC_TEMPLATE, TEMPLATE)?;
-        Ok(Render { template })
+        template.add_raw_template(TOC_TEMPLATE, &toc.source.load()?)?;
+        validate_template(template.get_template(TOC_TEMPLATE)?)?;
+        Ok(Render {
+            template,
+            style: toc.style.clone(),
+        })
     }
-    pub fn write<P: AsRef<Path>>(&self, param: RenderParam<P>) -> anyhow::Result<()> {
-        let toc = vec![param.visit, param.form];
+    /// render toc into html, entries are escaped by tera so the page works without javascript
+    pub fn render_html(
+        &self,
+        toc: &[RenderData],
+        schedule: Option<&str>,
+    ) -> anyhow::Result<String> {
         let mut context = Context::new();
-        context.insert("content", &serde_json::to_string(&toc)?);
+        context.insert("toc", toc);
+        context.insert("schedule", &schedule);
+        context.insert("style", &self.style);
+        Ok(self.template.render(TOC_TEMPLATE, &context)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, param: RenderParam<P>) -> anyhow::Result<()> {
         let html_file_name = param
             .destination
             .as_ref()
@@ -39,7 +56,7 @@ impl Render {
             .join(html_file_name);
         fs::write(
             html.as_path(),
-            self.template.render(TOC_TEMPLATE, &context)?,
+            self.render_html(&param.toc, param.schedule.as_deref())?,
         )?;
         html_to_pdf(html.as_path(), param.destination.as_ref())?;
         Ok(())
@@ -52,6 +69,8 @@ pub struct RenderData {
     pub(crate) kind: Level,
     pub(crate) name: String,
     pub(crate) page: Option<usize>,
+    /// page label of entry in acrf, such as `12`
+    pub(crate) label: Option<String>,
     pub(crate) children: Option<Vec<RenderData>>,
 }
 
@@ -61,15 +80,29 @@ pub enum Level {
     LEVEL1,
     LEVEL2,
     LEVEL3,
+    LEVEL4,
 }
 
 pub(crate) struct RenderParam<P: AsRef<Path>> {
-    pub(crate) visit: RenderData,
-    pub(crate) form: RenderData,
+    pub(crate) toc: Vec<RenderData>,
+    /// html table of schedule of assessments
+    pub(crate) schedule: Option<String>,
     pub(crate) destination: P,
 }
 
 impl RenderData {
+    /// label entries by their page in crf body
+    pub(crate) fn update_labels(&mut self, label: &PageLabel) {
+        if let Some(page) = self.page {
+            self.label = Some(label.format(page.saturating_sub(1)));
+        }
+        if let Some(children) = self.children.as_mut() {
+            for child in children.iter_mut() {
+                child.update_labels(label);
+            }
+        }
+    }
+
     pub(crate) fn update_pages(&mut self, base: usize) {
         if let Some(page) = self.page {
             self.page = Some(base.add(page).sub(1));
@@ -110,3 +143,59 @@ pub fn html_to_pdf<P: AsRef<Path>>(source: P, destination: P) -> anyhow::Result<
     fs::write(destination, pdf)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_html_test() -> anyhow::Result<()> {
+        let render = Render::new(&TocTemplate::default())?;
+        let toc = vec![RenderData {
+            id: None,
+            kind: Level::LEVEL1,
+            name: "Forms".into(),
+            page: None,
+            label: None,
+            children: Some(vec![RenderData {
+                id: None,
+                kind: Level::LEVEL2,
+                name: "Vital's \\ </script>".into(),
+                page: None,
+                label: None,
+                children: Some(vec![RenderData {
+                    id: Some(18),
+                    kind: Level::LEVEL3,
+                    name: "V1".into(),
+                    page: Some(46),
+                    label: Some("46".into()),
+                    children: None,
+                }]),
+            }]),
+        }];
+        let html = render.render_html(&toc, None)?;
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("</script>"));
+        assert!(html.contains("Vital&#x27;s"));
+        assert!(html.contains(r##"id="18_46" href="#18_46""##));
+        assert!(html.contains(r#"<span class="label">46</span>"#));
+        assert!(html.contains(r#"class="LEVEL1""#));
+        assert!(!html.contains("<table"));
+
+        let html = render.render_html(&toc, Some("<table class=\"schedule\"></table>"))?;
+        assert!(html.contains(r#"<table class="schedule">"#));
+        Ok(())
+    }
+
+    #[test]
+    fn render_style_test() -> anyhow::Result<()> {
+        let mut toc = TocTemplate::default();
+        toc.style.font_family = r#""Microsoft YaHei", 'SimSun', sans-serif"#.into();
+        let html = Render::new(&toc)?.render_html(&[], None)?;
+        assert!(html.contains(r#"font-family: "Microsoft YaHei", 'SimSun', sans-serif;"#));
+
+        toc.style.font_family = "serif; } </style>".into();
+        assert!(Render::new(&toc).is_err());
+        Ok(())
+    }
+}
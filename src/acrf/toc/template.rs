@@ -1,4 +1,28 @@
-pub const TEMPLATE: &str = r#"
+use anyhow::bail;
+use serde::Serialize;
+use std::{collections::HashSet, fs, path::PathBuf};
+use tera::{
+    ast::{Expr, ExprVal, Node},
+    Template,
+};
+
+/// variables which must be referenced by every toc template
+pub const REQUIRED_VARIABLES: &[&str] = &["toc"];
+
+pub const TEMPLATE: &str = r##"
+{% macro entry(data) %}
+{% if data.children %}
+<div class="{{ data.kind }}">
+    <span>{{ data.name }}</span>
+    {% for child in data.children %}{{ self::entry(data=child) }}{% endfor %}
+</div>
+{% else %}
+<div>
+    <a class="{{ data.kind }}" id="{{ data.id }}_{{ data.page }}" href="#{{ data.id }}_{{ data.page }}">{{ data.name }}</a>
+    {% if data.label %}<span class="label">{{ data.label }}</span>{% endif %}
+</div>
+{% endif %}
+{% endmacro entry %}
 <!DOCTYPE html>
 <html lang="en">
 
@@ -6,23 +30,57 @@ pub const TEMPLATE: &str = r#"
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
     <style>
+        body {
+            font-family: {{ style.font_family | safe }};
+        }
+
         .LEVEL1 {
-            font-size: 25px;
+            font-size: {{ style.title_font_size | safe }};
             font-weight: bold;
         }
 
-        .LEVEL2 {
-            font-size: 20px;
-            margin-left: 55px;
+        .LEVEL2,
+        .LEVEL3,
+        .LEVEL4 {
+            font-size: {{ style.font_size | safe }};
             font-weight: normal;
         }
 
-        .LEVEL3 {
-            font-size: 20px;
+        div.LEVEL2,
+        div.LEVEL3 {
+            margin-left: 55px;
+        }
+
+        a.LEVEL3,
+        a.LEVEL4 {
             margin-left: 50px;
             text-decoration: none;
+            color: {{ style.link_color | safe }};
+        }
+
+        span.label {
+            float: right;
+            font-size: {{ style.font_size | safe }};
+        }
+
+        table.schedule {
+            border-collapse: collapse;
+            font-size: {{ style.font_size | safe }};
+        }
+
+        table.schedule th,
+        table.schedule td {
+            border: 1px solid black;
+            padding: 2px 4px;
+        }
+
+        table.schedule td {
+            text-align: center;
+        }
+
+        table.schedule tbody th {
+            text-align: left;
             font-weight: normal;
-            color: blue;
         }
 
         .break-page {
@@ -31,48 +89,239 @@ pub const TEMPLATE: &str = r#"
 
         @media print {
             @page {
-                size: A4;
-                margin: 40px;
+                size: {{ style.page_size | safe }}{% if style.landscape %} landscape{% endif %};
+                margin: {{ style.margin | safe }};
             }
         }
     </style>
 </head>
 
 <body>
-    <div id="visit"></div>
+    {% for section in toc %}
+    {% if not loop.first %}<div class="break-page"></div>{% endif %}
+    {{ self::entry(data=section) }}
+    {% endfor %}
+    {% if schedule %}
     <div class="break-page"></div>
-    <div id="forms"></div>
+    {{ schedule | safe }}
+    {% endif %}
 </body>
 
-<script type="text/javascript">
-    const data = JSON.parse('{{ content }}');
-    const visit = data[0];
-    const forms = data[1];
-
-    function render(data) {
-        let root = document.createElement("div");
-        if (!data.children) {
-            let link = document.createElement("a");
-            link.innerText = data.name;
-            link.href = `#${data.id}`;
-            link.id = data.id;
-            link.className = data.kind;
-            root.appendChild(link);
-            return root;
-        }
-        let title = document.createElement("span");
-        title.innerText = data.name;
-        root.appendChild(title);
-        data.children.forEach(child => {
-            root.appendChild(render(child));
-        });
-        root.className = data.kind;
-        return root;
+</html>
+"##;
+
+/// toc template and the page settings passed into it
+#[derive(Debug, Clone, Default)]
+pub struct TocTemplate {
+    pub source: TemplateSource,
+    pub style: TocStyle,
+}
+
+/// where to load the toc template from, the built in template is used by default
+#[derive(Debug, Clone, Default)]
+pub enum TemplateSource {
+    #[default]
+    Builtin,
+    File(PathBuf),
+    Raw(String),
+}
+
+/// page size, margins and fonts, available in template as `style`, values are checked by
+/// `validate` so that templates can write them into css with `safe`
+#[derive(Debug, Clone, Serialize)]
+pub struct TocStyle {
+    /// css page size, such as `A4`, `Letter` or `210mm 297mm`
+    pub page_size: String,
+    pub landscape: bool,
+    /// css margin of page, such as `40px` or `2cm 1.5cm`
+    pub margin: String,
+    pub font_family: String,
+    pub title_font_size: String,
+    pub font_size: String,
+    pub link_color: String,
+}
+
+impl Default for TocStyle {
+    fn default() -> Self {
+        TocStyle {
+            page_size: "A4".into(),
+            landscape: false,
+            margin: "40px".into(),
+            font_family: "sans-serif".into(),
+            title_font_size: "25px".into(),
+            font_size: "20px".into(),
+            link_color: "blue".into(),
+        }
     }
+}
 
-    document.getElementById("visit").appendChild(render(visit));
-    document.getElementById("forms").appendChild(render(forms));
-</script>
+impl TocStyle {
+    /// reject values which could end a css declaration or the style element, such as `;` or `</`
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let values = [
+            ("page_size", &self.page_size),
+            ("margin", &self.margin),
+            ("font_family", &self.font_family),
+            ("title_font_size", &self.title_font_size),
+            ("font_size", &self.font_size),
+            ("link_color", &self.link_color),
+        ];
+        for (name, value) in values {
+            if value
+                .chars()
+                .any(|c| c.is_control() || matches!(c, '<' | '>' | '{' | '}' | ';' | '\\'))
+            {
+                bail!("invalid toc style {}: {}", name, value);
+            }
+        }
+        Ok(())
+    }
+}
 
-</html>
-"#;
+impl TemplateSource {
+    pub fn load(&self) -> anyhow::Result<String> {
+        Ok(match self {
+            TemplateSource::Builtin => TEMPLATE.into(),
+            TemplateSource::File(path) => fs::read_to_string(path)?,
+            TemplateSource::Raw(content) => content.clone(),
+        })
+    }
+}
+
+/// make sure template references all the required variables
+pub fn validate_template(template: &Template) -> anyhow::Result<()> {
+    let mut variables = HashSet::new();
+    collect_nodes(&template.ast, &mut variables);
+    for macro_definition in template.macros.values() {
+        collect_nodes(&macro_definition.body, &mut variables);
+    }
+    let missing = REQUIRED_VARIABLES
+        .iter()
+        .filter(|v| !variables.contains(**v))
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        bail!(
+            "toc template {} missing required variables: {}",
+            template.name,
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn collect_nodes(nodes: &[Node], variables: &mut HashSet<String>) {
+    for node in nodes {
+        match node {
+            Node::VariableBlock(_, expr) => collect_expr(expr, variables),
+            Node::Set(_, set) => collect_expr(&set.value, variables),
+            Node::MacroDefinition(_, definition, _) => collect_nodes(&definition.body, variables),
+            Node::FilterSection(_, section, _) => collect_nodes(&section.body, variables),
+            Node::Block(_, block, _) => collect_nodes(&block.body, variables),
+            Node::Forloop(_, forloop, _) => {
+                collect_expr(&forloop.container, variables);
+                collect_nodes(&forloop.body, variables);
+                if let Some(body) = forloop.empty_body.as_ref() {
+                    collect_nodes(body, variables);
+                }
+            }
+            Node::If(condition, _) => {
+                for (_, expr, body) in condition.conditions.iter() {
+                    collect_expr(expr, variables);
+                    collect_nodes(body, variables);
+                }
+                if let Some((_, body)) = condition.otherwise.as_ref() {
+                    collect_nodes(body, variables);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_expr(expr: &Expr, variables: &mut HashSet<String>) {
+    match &expr.val {
+        ExprVal::Ident(ident) => {
+            variables.insert(root_ident(ident));
+        }
+        ExprVal::Math(math) => {
+            collect_expr(&math.lhs, variables);
+            collect_expr(&math.rhs, variables);
+        }
+        ExprVal::Logic(logic) => {
+            collect_expr(&logic.lhs, variables);
+            collect_expr(&logic.rhs, variables);
+        }
+        ExprVal::In(contains) => {
+            collect_expr(&contains.lhs, variables);
+            collect_expr(&contains.rhs, variables);
+        }
+        ExprVal::Test(test) => {
+            variables.insert(root_ident(&test.ident));
+            test.args.iter().for_each(|e| collect_expr(e, variables));
+        }
+        ExprVal::MacroCall(call) => call.args.values().for_each(|e| collect_expr(e, variables)),
+        ExprVal::FunctionCall(call) => call.args.values().for_each(|e| collect_expr(e, variables)),
+        ExprVal::Array(items) => items.iter().for_each(|e| collect_expr(e, variables)),
+        ExprVal::StringConcat(concat) => {
+            for value in concat.values.iter() {
+                if let ExprVal::Ident(ident) = value {
+                    variables.insert(root_ident(ident));
+                }
+            }
+        }
+        _ => {}
+    }
+    for filter in expr.filters.iter() {
+        filter
+            .args
+            .values()
+            .for_each(|e| collect_expr(e, variables));
+    }
+}
+
+/// `style.font_size` and `data[0]` both refer to the variable before the accessor
+fn root_ident(ident: &str) -> String {
+    ident
+        .split(['.', '['])
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_template_test() -> anyhow::Result<()> {
+        let builtin = Template::new("builtin", None, TEMPLATE)?;
+        assert!(validate_template(&builtin).is_ok());
+
+        let nested = Template::new(
+            "nested",
+            None,
+            "{% if style.landscape %}{% for s in toc %}{{ s.name }}{% endfor %}{% endif %}",
+        )?;
+        assert!(validate_template(&nested).is_ok());
+
+        let missing = Template::new("missing", None, "<p>{{ style.page_size }}</p>")?;
+        assert!(validate_template(&missing).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn validate_style_test() {
+        let mut style = TocStyle {
+            font_family: r#""Microsoft YaHei", 'SimSun', sans-serif"#.into(),
+            link_color: "#0000ff".into(),
+            ..Default::default()
+        };
+        assert!(style.validate().is_ok());
+        style.font_family = "serif; } </style><script>".into();
+        assert!(style.validate().is_err());
+        style.font_family = "serif".into();
+        style.margin = "1cm\n2cm".into();
+        assert!(style.validate().is_err());
+    }
+}
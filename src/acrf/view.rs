@@ -0,0 +1,393 @@
+use chrono::{DateTime, Utc};
+use lopdf::{dictionary, text_string, Document, Object, ObjectId, Stream};
+use quick_xml::escape::escape;
+use std::collections::HashSet;
+
+/// panel shown when document is opened
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PageMode {
+    UseNone,
+    #[default]
+    UseOutlines,
+    UseThumbs,
+}
+
+/// zoom of the first page when document is opened
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum OpenZoom {
+    #[default]
+    FitWidth,
+    FitPage,
+    /// keep zoom of viewer
+    Inherit,
+}
+
+/// how viewers open the document, defaults open bookmarks panel with top level bookmarks expanded
+#[derive(Debug, Clone)]
+pub struct ViewOptions {
+    pub page_mode: PageMode,
+    /// zoom of page 1 when opened, viewers decide if not specified
+    pub open_zoom: Option<OpenZoom>,
+    /// levels of bookmarks expanded when opened, 0 collapses all bookmarks
+    pub bookmark_depth: usize,
+}
+
+impl Default for ViewOptions {
+    fn default() -> Self {
+        ViewOptions {
+            page_mode: PageMode::UseOutlines,
+            open_zoom: Some(OpenZoom::FitWidth),
+            bookmark_depth: 1,
+        }
+    }
+}
+
+/// document properties written to `/Info` and xmp metadata, replacing those of source
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    /// shown as window title of viewers instead of file name
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Vec<String>,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Metadata {
+            title: Some("Annotated Case Report Form".into()),
+            author: None,
+            subject: None,
+            keywords: vec![],
+        }
+    }
+}
+
+const PRODUCER: &str = concat!("reflector ", env!("CARGO_PKG_VERSION"));
+
+/// set page mode, open action and expansion of bookmarks
+pub(crate) fn write_view(doc: &mut Document, options: &ViewOptions) -> anyhow::Result<()> {
+    let first_page = doc.get_pages().get(&1).copied();
+    let counts = outline_counts(doc, options.bookmark_depth);
+    for (id, count) in counts {
+        doc.get_dictionary_mut(id)?.set("Count", count);
+    }
+
+    let catalog = doc.catalog_mut()?;
+    let page_mode = match options.page_mode {
+        PageMode::UseNone => "UseNone",
+        PageMode::UseOutlines => "UseOutlines",
+        PageMode::UseThumbs => "UseThumbs",
+    };
+    catalog.set("PageMode", page_mode);
+    match (options.open_zoom.as_ref(), first_page) {
+        (Some(zoom), Some(page)) => {
+            let dest = match zoom {
+                OpenZoom::FitWidth => vec![page.into(), "FitH".into(), Object::Null],
+                OpenZoom::FitPage => vec![page.into(), "Fit".into()],
+                OpenZoom::Inherit => vec![
+                    page.into(),
+                    "XYZ".into(),
+                    Object::Null,
+                    Object::Null,
+                    Object::Null,
+                ],
+            };
+            catalog.set("OpenAction", dest);
+        }
+        _ => {
+            catalog.remove(b"OpenAction");
+        }
+    }
+    Ok(())
+}
+
+/// `/Count` of outline items and outline root, items above `depth` are open and show the
+/// count of their visible descendants, closed items show the negative count
+fn outline_counts(doc: &Document, depth: usize) -> Vec<(ObjectId, i64)> {
+    let Ok(root) = doc
+        .catalog()
+        .and_then(|c| c.get(b"Outlines"))
+        .and_then(Object::as_reference)
+    else {
+        return vec![];
+    };
+    let mut counts = vec![];
+    let mut visited = HashSet::new();
+    let first = first_child(doc, root);
+    let visible = count_items(doc, first, 1, depth, &mut visited, &mut counts);
+    counts.push((root, visible as i64));
+    counts
+}
+
+fn first_child(doc: &Document, id: ObjectId) -> Option<ObjectId> {
+    doc.get_dictionary(id)
+        .and_then(|d| d.get(b"First"))
+        .and_then(Object::as_reference)
+        .ok()
+}
+
+/// count items visible in the chain of siblings from `first`, including open descendants
+fn count_items(
+    doc: &Document,
+    first: Option<ObjectId>,
+    level: usize,
+    depth: usize,
+    visited: &mut HashSet<ObjectId>,
+    counts: &mut Vec<(ObjectId, i64)>,
+) -> usize {
+    let mut visible = 0;
+    let mut next = first;
+    while let Some(id) = next.filter(|id| visited.insert(*id)) {
+        visible += 1;
+        if let Some(child) = first_child(doc, id) {
+            let descendants = count_items(doc, Some(child), level + 1, depth, visited, counts);
+            if level <= depth {
+                counts.push((id, descendants as i64));
+                visible += descendants;
+            } else {
+                counts.push((id, -(descendants as i64)));
+            }
+        }
+        next = doc
+            .get_dictionary(id)
+            .and_then(|d| d.get(b"Next"))
+            .and_then(Object::as_reference)
+            .ok();
+    }
+    visible
+}
+
+/// replace `/Info` and xmp metadata of document
+pub(crate) fn write_metadata(doc: &mut Document, metadata: &Metadata) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let mut info = dictionary! {
+        "Producer" => text_string(PRODUCER),
+        "CreationDate" => Object::string_literal(pdf_date(&now)),
+        "ModDate" => Object::string_literal(pdf_date(&now)),
+    };
+    let keywords = metadata.keywords.join("; ");
+    let fields = [
+        ("Title", metadata.title.as_deref()),
+        ("Author", metadata.author.as_deref()),
+        ("Subject", metadata.subject.as_deref()),
+        (
+            "Keywords",
+            Some(keywords.as_str()).filter(|k| !k.is_empty()),
+        ),
+    ];
+    for (key, value) in fields {
+        if let Some(value) = value {
+            info.set(key, text_string(value));
+        }
+    }
+    let info_id = doc.add_object(info);
+    doc.trailer.set("Info", info_id);
+
+    let mut stream = Stream::new(
+        dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+        xmp(metadata, &now).into_bytes(),
+    );
+    // xmp is left uncompressed so that it can be found without parsing pdf
+    stream.allows_compression = false;
+    let metadata_id = doc.add_object(stream);
+    let catalog = doc.catalog_mut()?;
+    catalog.set("Metadata", metadata_id);
+    if metadata.title.is_some() {
+        // other viewer preferences are kept, the dictionary may be inline or a reference
+        match catalog.get(b"ViewerPreferences").cloned() {
+            Ok(Object::Reference(id)) => doc.get_dictionary_mut(id)?.set("DisplayDocTitle", true),
+            Ok(Object::Dictionary(mut preferences)) => {
+                preferences.set("DisplayDocTitle", true);
+                catalog.set("ViewerPreferences", preferences);
+            }
+            _ => catalog.set(
+                "ViewerPreferences",
+                dictionary! { "DisplayDocTitle" => true },
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn pdf_date(date: &DateTime<Utc>) -> String {
+    date.format("D:%Y%m%d%H%M%SZ").to_string()
+}
+
+fn xmp(metadata: &Metadata, date: &DateTime<Utc>) -> String {
+    let alt = |value: &str| {
+        format!(
+            "<rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt>",
+            escape(value)
+        )
+    };
+    let mut properties = vec![];
+    if let Some(title) = metadata.title.as_deref() {
+        properties.push(format!("<dc:title>{}</dc:title>", alt(title)));
+    }
+    if let Some(author) = metadata.author.as_deref() {
+        properties.push(format!(
+            "<dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>",
+            escape(author)
+        ));
+    }
+    if let Some(subject) = metadata.subject.as_deref() {
+        properties.push(format!("<dc:description>{}</dc:description>", alt(subject)));
+    }
+    if !metadata.keywords.is_empty() {
+        properties.push(format!(
+            "<pdf:Keywords>{}</pdf:Keywords>",
+            escape(metadata.keywords.join("; "))
+        ));
+    }
+    let date = date.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    properties.push(format!("<pdf:Producer>{}</pdf:Producer>", PRODUCER));
+    properties.push(format!("<xmp:CreateDate>{}</xmp:CreateDate>", date));
+    properties.push(format!("<xmp:ModifyDate>{}</xmp:ModifyDate>", date));
+    format!(
+        r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+<rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:pdf="http://ns.adobe.com/pdf/1.3/" xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+{}
+</rdf:Description>
+</rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#,
+        properties.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acrf::annotate::tests::blank_ecrf;
+
+    #[test]
+    fn write_view_test() -> anyhow::Result<()> {
+        let mut doc = blank_ecrf(&[&[], &[]]);
+        // Forms -> [Demographics -> [V1, V2]]
+        let root = doc.new_object_id();
+        let section = doc.new_object_id();
+        let form = doc.new_object_id();
+        let v2 = doc.add_object(dictionary! { "Title" => text_string("V2"), "Parent" => form });
+        let v1 = doc.add_object(dictionary! {
+            "Title" => text_string("V1"),
+            "Parent" => form,
+            "Next" => v2,
+        });
+        doc.objects.insert(
+            form,
+            Object::Dictionary(dictionary! {
+                "Title" => text_string("Demographics"),
+                "Parent" => section,
+                "First" => v1,
+                "Last" => v2,
+            }),
+        );
+        doc.objects.insert(
+            section,
+            Object::Dictionary(dictionary! {
+                "Title" => text_string("Forms"),
+                "Parent" => root,
+                "First" => form,
+                "Last" => form,
+            }),
+        );
+        doc.objects.insert(
+            root,
+            Object::Dictionary(dictionary! { "First" => section, "Last" => section }),
+        );
+        doc.catalog_mut()?.set("Outlines", root);
+
+        write_view(&mut doc, &ViewOptions::default())?;
+        let count = |id| {
+            doc.get_dictionary(id)
+                .unwrap()
+                .get(b"Count")
+                .unwrap()
+                .as_i64()
+                .unwrap()
+        };
+        assert_eq!(count(section), 1);
+        assert_eq!(count(form), -2);
+        assert_eq!(count(root), 2);
+        let catalog = doc.catalog()?;
+        assert_eq!(catalog.get(b"PageMode")?.as_name()?, b"UseOutlines");
+        assert_eq!(
+            catalog.get(b"OpenAction")?.as_array()?[1].as_name()?,
+            b"FitH"
+        );
+
+        let options = ViewOptions {
+            page_mode: PageMode::UseNone,
+            open_zoom: None,
+            bookmark_depth: 2,
+        };
+        write_view(&mut doc, &options)?;
+        let count = |id| {
+            doc.get_dictionary(id)
+                .unwrap()
+                .get(b"Count")
+                .unwrap()
+                .as_i64()
+                .unwrap()
+        };
+        assert_eq!(count(section), 3);
+        assert_eq!(count(form), 2);
+        assert_eq!(count(root), 4);
+        assert!(doc.catalog()?.get(b"OpenAction").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn write_metadata_test() -> anyhow::Result<()> {
+        let mut doc = blank_ecrf(&[&[]]);
+        let metadata = Metadata {
+            title: Some("AK112-301 aCRF".into()),
+            author: Some("Akeso & Partners".into()),
+            subject: None,
+            keywords: vec!["aCRF".into(), "SDTM".into()],
+        };
+        write_metadata(&mut doc, &metadata)?;
+        let info = doc.get_dictionary(doc.trailer.get(b"Info")?.as_reference()?)?;
+        let text = |key: &[u8]| lopdf::decode_text_string(info.get(key).unwrap()).unwrap();
+        assert_eq!(text(b"Title"), "AK112-301 aCRF");
+        assert_eq!(text(b"Keywords"), "aCRF; SDTM");
+        assert!(info.get(b"Subject").is_err());
+
+        let catalog = doc.catalog()?;
+        let xmp = doc
+            .get_object(catalog.get(b"Metadata")?.as_reference()?)?
+            .as_stream()?;
+        let xmp = String::from_utf8(xmp.content.clone())?;
+        assert!(xmp.contains("<rdf:li>Akeso &amp; Partners</rdf:li>"));
+        assert!(xmp.contains("<pdf:Keywords>aCRF; SDTM</pdf:Keywords>"));
+        let preferences = catalog.get(b"ViewerPreferences")?.as_dict()?;
+        assert_eq!(preferences.get(b"DisplayDocTitle")?, &Object::Boolean(true));
+
+        // existing viewer preferences are kept, inline or referenced
+        let preferences = dictionary! { "HideToolbar" => true, "DisplayDocTitle" => false };
+        let mut doc = blank_ecrf(&[&[]]);
+        doc.catalog_mut()?
+            .set("ViewerPreferences", preferences.clone());
+        write_metadata(&mut doc, &metadata)?;
+        let inline = doc.catalog()?.get(b"ViewerPreferences")?.as_dict()?;
+        assert_eq!(inline.get(b"HideToolbar")?, &Object::Boolean(true));
+        assert_eq!(inline.get(b"DisplayDocTitle")?, &Object::Boolean(true));
+
+        let mut doc = blank_ecrf(&[&[]]);
+        let preferences_id = doc.add_object(preferences);
+        doc.catalog_mut()?.set("ViewerPreferences", preferences_id);
+        write_metadata(&mut doc, &metadata)?;
+        let catalog = doc.catalog()?;
+        assert_eq!(
+            catalog.get(b"ViewerPreferences")?.as_reference()?,
+            preferences_id
+        );
+        let referenced = doc.get_dictionary(preferences_id)?;
+        assert_eq!(referenced.get(b"HideToolbar")?, &Object::Boolean(true));
+        assert_eq!(referenced.get(b"DisplayDocTitle")?, &Object::Boolean(true));
+        Ok(())
+    }
+}
@@ -1,2 +1,6 @@
+pub mod bundle;
 pub mod config;
 pub mod controller;
+pub mod history;
+pub mod schema;
+pub mod store;
@@ -0,0 +1,590 @@
+use super::{
+    config::{sha256, ConfigList},
+    controller::ConfigController,
+    schema,
+    store::{write_atomic, write_staged},
+};
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fs::{create_dir_all, read, remove_file, rename, File},
+    io::{Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+const BUNDLE_VERSION: u32 = 1;
+const MANIFEST: &str = "manifest.json";
+
+/// index of bundle archive, configs are stored as `config/<id>.json`
+/// and source files as `sources/<sha256>/<name>`
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    exported_at: DateTime<Utc>,
+    configs: Vec<ConfigList>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// directory to look up source files of configs by name, sources are not bundled if not specified
+    pub sources: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    pub collision: Collision,
+    /// directory to extract bundled source files into, sources are skipped if not specified
+    pub sources: Option<PathBuf>,
+}
+
+/// what to do if id of imported config or name of extracted source file exists already
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Collision {
+    /// import config with a new id, write source file of different content under a new name
+    #[default]
+    Rename,
+    /// replace existing config, its history is kept, and existing source file
+    Overwrite,
+}
+
+/// result of importing bundle
+#[derive(Debug, Default)]
+pub struct Import {
+    /// ids of imported configs in the order of bundle
+    pub configs: Vec<String>,
+    /// source files written under a new name as (name in bundle, new name),
+    /// since a different file of the same name exists
+    pub renamed: Vec<(String, String)>,
+}
+
+impl ConfigController {
+    /// write configs, their list entries and optionally their source files into a zip archive
+    pub fn export<P: AsRef<Path>>(
+        &self,
+        ids: &[String],
+        path: P,
+        options: &ExportOptions,
+    ) -> anyhow::Result<()> {
+        let list = self.list_config()?;
+        let configs = ids
+            .iter()
+            .map(|id| {
+                list.iter()
+                    .find(|c| c.id.eq(id))
+                    .cloned()
+                    .ok_or(anyhow!("config {} not found", id))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let file_options =
+            SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        let mut bundled = HashSet::new();
+        for config in configs.iter() {
+            zip.start_file(format!("config/{}.json", config.id), file_options)?;
+            zip.write_all(&schema::to_vec(&self.get_config(&config.id)?)?)?;
+            let Some(dir) = options.sources.as_ref() else {
+                continue;
+            };
+            for source in config.sources.iter() {
+                if !bundled.insert(source.sha256.clone()) {
+                    continue;
+                }
+                let content = read(dir.join(&source.name))?;
+                if sha256(&mut content.as_slice())?.ne(&source.sha256) {
+                    bail!(
+                        "source file {} of config {} does not match recorded hash",
+                        source.name,
+                        config.name
+                    );
+                }
+                zip.start_file(
+                    format!("sources/{}/{}", source.sha256, source.name),
+                    file_options,
+                )?;
+                zip.write_all(&content)?;
+            }
+        }
+        let manifest = Manifest {
+            version: BUNDLE_VERSION,
+            exported_at: Utc::now(),
+            configs,
+        };
+        zip.start_file(MANIFEST, file_options)?;
+        zip.write_all(&serde_json::to_vec(&manifest)?)?;
+        let content = zip.finish()?.into_inner();
+        write_atomic(path, &content)
+    }
+
+    /// import configs of bundle, nothing is imported if any config fails validation
+    /// or any of them fails to be saved
+    pub fn import<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &ImportOptions,
+    ) -> anyhow::Result<Import> {
+        let mut zip = ZipArchive::new(File::open(path.as_ref())?)?;
+        let manifest = serde_json::from_slice::<Manifest>(&read_entry(&mut zip, MANIFEST)?)?;
+        if manifest.version > BUNDLE_VERSION {
+            bail!(
+                "bundle version {} is newer than supported version {}",
+                manifest.version,
+                BUNDLE_VERSION
+            );
+        }
+
+        // names in manifest become paths of written files, so they are checked before anything is written
+        for entry in manifest.configs.iter() {
+            if !is_safe_id(&entry.id) {
+                bail!("invalid config id {} in bundle", entry.id);
+            }
+            for source in entry.sources.iter() {
+                if !is_file_name(&source.name) {
+                    bail!("invalid source file name {} in bundle", source.name);
+                }
+            }
+        }
+
+        let mut configs = Vec::with_capacity(manifest.configs.len());
+        let mut errors = vec![];
+        for entry in manifest.configs {
+            let config =
+                schema::from_slice(&read_entry(&mut zip, &format!("config/{}.json", entry.id))?)?;
+            errors.extend(
+                config
+                    .validate(None)
+                    .into_iter()
+                    .filter(|d| d.is_error())
+                    .map(|d| format!("{}: {}", entry.name, d.message)),
+            );
+            configs.push((entry, config));
+        }
+        if !errors.is_empty() {
+            bail!("invalid configs in bundle:\n{}", errors.join("\n"));
+        }
+
+        // list of (name, sha256, content), files of the same name must have the same content
+        let mut sources: Vec<(String, String, Vec<u8>)> = vec![];
+        if options.sources.is_some() {
+            for source in configs.iter().flat_map(|(entry, _)| entry.sources.iter()) {
+                if let Some((_, hash, _)) = sources.iter().find(|(n, _, _)| n.eq(&source.name)) {
+                    if hash.ne(&source.sha256) {
+                        bail!(
+                            "bundle has different source files of the same name {}",
+                            source.name
+                        );
+                    }
+                    continue;
+                }
+                let name = format!("sources/{}/{}", source.sha256, source.name);
+                if zip.by_name(&name).is_err() {
+                    continue;
+                }
+                let content = read_entry(&mut zip, &name)?;
+                if sha256(&mut content.as_slice())?.ne(&source.sha256) {
+                    bail!("source file {} in bundle is corrupted", source.name);
+                }
+                sources.push((source.name.clone(), source.sha256.clone(), content));
+            }
+        }
+
+        // new name of each bundled source file, decided before anything is written
+        let mut names = HashMap::new();
+        let mut import = Import::default();
+        if let Some(dir) = options.sources.as_ref() {
+            for (name, hash, _) in sources.iter() {
+                let conflicted = existing_hash(&dir.join(name))?.is_some_and(|h| h.ne(hash));
+                let target = if conflicted && options.collision.eq(&Collision::Rename) {
+                    renamed_source(name, hash)
+                } else {
+                    name.clone()
+                };
+                if target.ne(name) {
+                    if sources.iter().any(|(n, _, _)| n.eq(&target)) {
+                        bail!("renamed source file {} is bundled already", target);
+                    }
+                    if existing_hash(&dir.join(&target))?.is_some_and(|h| h.ne(hash)) {
+                        bail!(
+                            "renamed source file {} exists with different content",
+                            target
+                        );
+                    }
+                    import.renamed.push((name.clone(), target.clone()));
+                }
+                names.insert(name.clone(), target);
+            }
+        }
+
+        // ids are checked against both stored configs and configs imported before in this bundle
+        let stored = self
+            .list_config()?
+            .into_iter()
+            .map(|c| c.id)
+            .collect::<HashSet<_>>();
+        let mut taken = HashSet::with_capacity(configs.len());
+        for (entry, _) in configs.iter_mut() {
+            let renamed = match options.collision {
+                Collision::Rename => stored.contains(&entry.id) || taken.contains(&entry.id),
+                // a config overwrites stored one, but never another config of the same bundle
+                Collision::Overwrite => taken.contains(&entry.id),
+            };
+            if renamed {
+                entry.id = nanoid!();
+                while stored.contains(&entry.id) || taken.contains(&entry.id) {
+                    entry.id = nanoid!();
+                }
+            }
+            taken.insert(entry.id.clone());
+            for source in entry.sources.iter_mut() {
+                if let Some(name) = names.get(&source.name) {
+                    source.name = name.clone();
+                }
+            }
+            import.configs.push(entry.id.clone());
+        }
+
+        // source files are staged before configs are saved, and moved into place only after
+        let mut staged = vec![];
+        if let Some(dir) = options.sources.as_ref() {
+            create_dir_all(dir)?;
+            for (name, hash, content) in sources.iter() {
+                let target = dir.join(&names[name]);
+                if existing_hash(&target)?.is_some_and(|h| h.eq(hash)) {
+                    continue;
+                }
+                match write_staged(&target, content) {
+                    Ok(temp) => staged.push((temp, target)),
+                    Err(e) => {
+                        for (temp, _) in staged {
+                            remove_file(temp).ok();
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        let message = format!(
+            "imported from {}",
+            path.as_ref()
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default()
+        );
+        if let Err(e) = self.store().save_all(configs, None, Some(message)) {
+            for (temp, _) in staged {
+                remove_file(temp).ok();
+            }
+            return Err(e);
+        }
+        for (temp, target) in staged {
+            rename(temp, target)?;
+        }
+        Ok(import)
+    }
+}
+
+/// sha256 of file, none if it does not exist
+fn existing_hash(path: &Path) -> anyhow::Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(sha256(&mut File::open(path)?)?))
+}
+
+/// name of source file written beside an existing file of the same name, such as `edc.1a2b3c4d.xlsx`
+fn renamed_source(name: &str, hash: &str) -> String {
+    let path = Path::new(name);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, &hash[..8], ext.to_string_lossy()),
+        None => format!("{}.{}", stem, &hash[..8]),
+    }
+}
+
+/// ids are file names of store, only characters of nanoid are allowed
+fn is_safe_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// plain file name without directory, such as `edc.xlsx` but not `../edc.xlsx` or `C:edc.xlsx`
+fn is_file_name(name: &str) -> bool {
+    !name.contains(['/', '\\', ':'])
+        && Path::new(name)
+            .file_name()
+            .is_some_and(|n| n.eq(OsStr::new(name)))
+}
+
+fn read_entry<R: Read + Seek>(zip: &mut ZipArchive<R>, name: &str) -> anyhow::Result<Vec<u8>> {
+    let mut entry = zip
+        .by_name(name)
+        .map_err(|_| anyhow!("{} not found in bundle", name))?;
+    let mut content = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{
+            config::{ConfigMeta, SourceFile},
+            store::tests::entry,
+        },
+        edc::db::tests::db,
+    };
+    use std::fs::{read_dir, remove_dir_all, write};
+
+    /// write bundle with the given manifest entries, each config is the fixture,
+    /// entries of the same id share one config file
+    fn write_bundle(path: &Path, configs: Vec<ConfigList>, sources: &[(&str, &str)]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        let options = SimpleFileOptions::default();
+        let mut written = HashSet::new();
+        for config in configs.iter().filter(|c| written.insert(c.id.clone())) {
+            zip.start_file(format!("config/{}.json", config.id), options)
+                .unwrap();
+            zip.write_all(&schema::to_vec(&db()).unwrap()).unwrap();
+        }
+        for (name, content) in sources {
+            let hash = sha256(&mut content.as_bytes()).unwrap();
+            zip.start_file(format!("sources/{}/{}", hash, name), options)
+                .unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        let manifest = Manifest {
+            version: BUNDLE_VERSION,
+            exported_at: Utc::now(),
+            configs,
+        };
+        zip.start_file(MANIFEST, options).unwrap();
+        zip.write_all(&serde_json::to_vec(&manifest).unwrap())
+            .unwrap();
+        zip.finish().unwrap();
+    }
+
+    fn source(name: &str, content: &str) -> SourceFile {
+        SourceFile {
+            name: name.into(),
+            sha256: sha256(&mut content.as_bytes()).unwrap(),
+        }
+    }
+
+    #[test]
+    fn export_import_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        let sources = root.join("sources");
+        create_dir_all(&sources)?;
+        write(sources.join("edc.xlsx"), "edc")?;
+        let meta = ConfigMeta {
+            study: Some("AK112-301".into()),
+            sources: vec![SourceFile::read(sources.join("edc.xlsx"))?],
+            ..Default::default()
+        };
+        let from = ConfigController::new(root.join("from"))?;
+        let id = from.save_config_with_meta(None, "bundled", &db(), &meta)?;
+        let bundle = root.join("bundle.zip");
+        let options = ExportOptions {
+            sources: Some(sources),
+        };
+        from.export(std::slice::from_ref(&id), &bundle, &options)?;
+
+        let to = ConfigController::new(root.join("to"))?;
+        let options = ImportOptions {
+            sources: Some(root.join("imported")),
+            ..Default::default()
+        };
+        assert_eq!(to.import(&bundle, &options)?.configs, vec![id.clone()]);
+        let list = to.list_config()?;
+        assert_eq!(list[0].study.as_deref(), Some("AK112-301"));
+        assert_eq!(to.get_config(&id)?.form.len(), 2);
+        assert!(root.join("imported").join("edc.xlsx").exists());
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn import_collision_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        let bundle = root.join("bundle.zip");
+        create_dir_all(&root)?;
+        write_bundle(&bundle, vec![entry("abc", "bundled")], &[]);
+        let to = ConfigController::new(root.join("to"))?;
+        let options = ImportOptions::default();
+        assert_eq!(to.import(&bundle, &options)?.configs, vec!["abc"]);
+        let renamed = to.import(&bundle, &options)?.configs;
+        assert_ne!(renamed[0], "abc");
+        assert_eq!(to.list_config()?.len(), 2);
+
+        let options = ImportOptions {
+            collision: Collision::Overwrite,
+            sources: None,
+        };
+        to.import(&bundle, &options)?;
+        assert_eq!(to.list_config()?.len(), 2);
+        assert_eq!(to.list_revisions("abc")?.len(), 2);
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn import_duplicate_ids_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        let bundle = root.join("bundle.zip");
+        create_dir_all(&root)?;
+        write_bundle(
+            &bundle,
+            vec![entry("abc", "first"), entry("abc", "second")],
+            &[],
+        );
+        for collision in [Collision::Rename, Collision::Overwrite] {
+            let to = ConfigController::new(root.join(nanoid!()))?;
+            let options = ImportOptions {
+                collision,
+                sources: None,
+            };
+            // configs of the same id in a bundle never overwrite each other
+            let imported = to.import(&bundle, &options)?.configs;
+            assert_eq!(imported[0], "abc");
+            assert_ne!(imported[1], "abc");
+            let list = to.list_config()?;
+            assert_eq!(list.len(), 2);
+            assert_eq!(list[1].name, "second");
+            assert_eq!(to.list_revisions("abc")?.len(), 1);
+        }
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn import_invalid_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        let from = ConfigController::new(root.join("from"))?;
+        let mut invalid = db();
+        invalid.binding[0].children.push(9);
+        let id = from.save_config(None, "invalid", &invalid)?;
+        let bundle = root.join("bundle.zip");
+        from.export(&[id], &bundle, &ExportOptions::default())?;
+
+        // invalid configs are rejected as a whole
+        let to = ConfigController::new(root.join("to"))?;
+        assert!(to.import(&bundle, &ImportOptions::default()).is_err());
+        assert!(to.list_config()?.is_empty());
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn import_unsafe_names_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        create_dir_all(&root)?;
+        let bundle = root.join("bundle.zip");
+        let to = ConfigController::new(root.join("to"))?;
+        let options = ImportOptions {
+            sources: Some(root.join("imported")),
+            ..Default::default()
+        };
+        for name in [
+            "../escaped.xlsx",
+            "/tmp/escaped.xlsx",
+            "a\\b.xlsx",
+            "C:escaped.xlsx",
+        ] {
+            let mut config = entry("abc", "unsafe");
+            config.sources.push(source(name, "edc"));
+            write_bundle(&bundle, vec![config], &[(name, "edc")]);
+            assert!(to.import(&bundle, &options).is_err());
+        }
+        write_bundle(&bundle, vec![entry("../../escaped", "unsafe")], &[]);
+        assert!(to.import(&bundle, &options).is_err());
+        assert!(to.list_config()?.is_empty());
+        assert!(!root.join("escaped.xlsx").exists());
+        assert!(!root.join("imported").exists());
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn import_same_source_name_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        create_dir_all(&root)?;
+        let bundle = root.join("bundle.zip");
+        let to = ConfigController::new(root.join("to"))?;
+        let options = ImportOptions {
+            sources: Some(root.join("imported")),
+            ..Default::default()
+        };
+        let mut first = entry("first", "first");
+        first.sources.push(source("edc.xlsx", "v1"));
+        let mut second = entry("second", "second");
+        second.sources.push(source("edc.xlsx", "v2"));
+        write_bundle(
+            &bundle,
+            vec![first.clone(), second],
+            &[("edc.xlsx", "v1"), ("edc.xlsx", "v2")],
+        );
+        assert!(to.import(&bundle, &options).is_err());
+
+        // the same file shared by configs is written once
+        let mut second = entry("second", "second");
+        second.sources.push(source("edc.xlsx", "v1"));
+        write_bundle(&bundle, vec![first, second], &[("edc.xlsx", "v1")]);
+        assert_eq!(to.import(&bundle, &options)?.configs.len(), 2);
+        assert_eq!(
+            std::fs::read_to_string(root.join("imported").join("edc.xlsx"))?,
+            "v1"
+        );
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn import_existing_source_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        let imported = root.join("imported");
+        create_dir_all(&imported)?;
+        write(imported.join("edc.xlsx"), "local")?;
+        let bundle = root.join("bundle.zip");
+        let mut config = entry("abc", "bundled");
+        config.sources.push(source("edc.xlsx", "v1"));
+        write_bundle(&bundle, vec![config], &[("edc.xlsx", "v1")]);
+
+        // existing file of different content is kept, bundled one is written beside it
+        let to = ConfigController::new(root.join("to"))?;
+        let options = ImportOptions {
+            sources: Some(imported.clone()),
+            ..Default::default()
+        };
+        let import = to.import(&bundle, &options)?;
+        let hash = source("edc.xlsx", "v1").sha256;
+        let renamed = format!("edc.{}.xlsx", &hash[..8]);
+        assert_eq!(import.renamed, vec![("edc.xlsx".into(), renamed.clone())]);
+        assert_eq!(std::fs::read_to_string(imported.join("edc.xlsx"))?, "local");
+        assert_eq!(std::fs::read_to_string(imported.join(&renamed))?, "v1");
+        assert_eq!(to.list_config()?[0].sources[0].name, renamed);
+        // importing again reuses the renamed file
+        assert_eq!(to.import(&bundle, &options)?.renamed.len(), 1);
+        assert_eq!(read_dir(&imported)?.count(), 2);
+
+        let options = ImportOptions {
+            collision: Collision::Overwrite,
+            sources: Some(imported.clone()),
+        };
+        assert!(to.import(&bundle, &options)?.renamed.is_empty());
+        assert_eq!(std::fs::read_to_string(imported.join("edc.xlsx"))?, "v1");
+        remove_dir_all(root)?;
+        Ok(())
+    }
+}
@@ -1,7 +1,193 @@
+use crate::edc::db::DBKind;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigList {
     pub id: String,
     pub name: String,
+    /// study the config belongs to, such as `AK112-301`
+    #[serde(default)]
+    pub study: Option<String>,
+    #[serde(default)]
+    pub kind: Option<DBKind>,
+    /// edc building file and ecrf the config was read from
+    #[serde(default)]
+    pub sources: Vec<SourceFile>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub created_by: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub updated_by: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceFile {
+    /// file name without directory
+    pub name: String,
+    /// lowercase hex of sha-256 digest of file content
+    pub sha256: String,
+}
+
+/// metadata recorded when saving config, fields left empty keep the recorded values
+#[derive(Debug, Clone, Default)]
+pub struct ConfigMeta {
+    pub study: Option<String>,
+    pub kind: Option<DBKind>,
+    pub sources: Vec<SourceFile>,
+    pub author: Option<String>,
+    /// message of the revision created by saving
+    pub message: Option<String>,
+}
+
+/// filter and sort of config list, filters left empty match all configs
+#[derive(Debug, Clone, Default)]
+pub struct ConfigQuery {
+    pub study: Option<String>,
+    pub kind: Option<DBKind>,
+    /// case insensitive substring of config name
+    pub name: Option<String>,
+    /// creator or last editor of config
+    pub author: Option<String>,
+    pub sort: ConfigSort,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ConfigSort {
+    /// order of saving
+    #[default]
+    Saved,
+    Name,
+    Study,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl SourceFile {
+    pub fn read<P: AsRef<Path>>(p: P) -> anyhow::Result<Self> {
+        let p = p.as_ref();
+        let sha256 = sha256(&mut File::open(p)?)?;
+        let name = p
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Ok(SourceFile { name, sha256 })
+    }
+}
+
+/// lowercase hex of sha-256 digest of content
+pub(crate) fn sha256<R: Read>(reader: &mut R) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+impl ConfigQuery {
+    pub fn matches(&self, config: &ConfigList) -> bool {
+        if self.study.is_some() && self.study.ne(&config.study) {
+            return false;
+        }
+        if self.kind.is_some() && self.kind.ne(&config.kind) {
+            return false;
+        }
+        if let Some(name) = self.name.as_ref() {
+            if !config.name.to_lowercase().contains(&name.to_lowercase()) {
+                return false;
+            }
+        }
+        if let Some(author) = self.author.as_ref() {
+            if config.created_by.as_ref().ne(&Some(author))
+                && config.updated_by.as_ref().ne(&Some(author))
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// filter and sort list, sort is stable so configs with equal keys keep the order of saving
+    pub fn apply(&self, list: Vec<ConfigList>) -> Vec<ConfigList> {
+        let mut list = list
+            .into_iter()
+            .filter(|c| self.matches(c))
+            .collect::<Vec<_>>();
+        match self.sort {
+            ConfigSort::Saved => {}
+            ConfigSort::Name => list.sort_by(|x, y| x.name.cmp(&y.name)),
+            ConfigSort::Study => list.sort_by(|x, y| x.study.cmp(&y.study)),
+            ConfigSort::CreatedAt => list.sort_by_key(|c| c.created_at),
+            ConfigSort::UpdatedAt => list.sort_by_key(|c| c.updated_at.or(c.created_at)),
+        }
+        if self.descending {
+            list.reverse();
+        }
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn config(id: &str, study: &str, kind: DBKind, day: u32) -> ConfigList {
+        ConfigList {
+            id: id.into(),
+            name: format!("{} aCRF", study),
+            study: Some(study.into()),
+            kind: Some(kind),
+            sources: vec![],
+            created_at: Some(Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap()),
+            created_by: Some("alice".into()),
+            updated_at: None,
+            updated_by: None,
+        }
+    }
+
+    #[test]
+    fn query_test() {
+        let list = vec![
+            config("a", "AK112-301", DBKind::Rave, 3),
+            config("b", "AK104-201", DBKind::ECollect, 1),
+            config("c", "AK112-301", DBKind::ECollect, 2),
+        ];
+        let query = ConfigQuery {
+            study: Some("AK112-301".into()),
+            sort: ConfigSort::CreatedAt,
+            ..Default::default()
+        };
+        let ids = query
+            .apply(list.clone())
+            .into_iter()
+            .map(|c| c.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["c", "a"]);
+
+        let query = ConfigQuery {
+            kind: Some(DBKind::ECollect),
+            name: Some("ak104".into()),
+            ..Default::default()
+        };
+        assert_eq!(query.apply(list.clone()).len(), 1);
+
+        let query = ConfigQuery {
+            author: Some("bob".into()),
+            ..Default::default()
+        };
+        assert!(query.apply(list).is_empty());
+    }
 }
@@ -1,36 +1,87 @@
-use super::config::ConfigList;
-use crate::edc::db::DBStruct;
-use nanoid::nanoid;
-use std::{
-    fs::{create_dir_all, read, remove_file, write, OpenOptions},
-    io::Write,
-    path::{Path, PathBuf},
+use super::{
+    config::{ConfigList, ConfigMeta, ConfigQuery},
+    history::{Revision, RevisionInfo},
+    store::{file::FileStore, ConfigStore, Migration, Repair, UpdateEntry},
+};
+use crate::{
+    ecrf::ecrf_reader,
+    edc::{
+        db::{db_reader, DBKind, DBStruct},
+        diff::Diff,
+        merge::{Merge, MergeOptions},
+        schedule::Schedule,
+    },
 };
+use anyhow::anyhow;
+use chrono::Utc;
+use nanoid::nanoid;
+use std::path::{Path, PathBuf};
 
 pub struct ConfigController {
+    /// root of json files if controller is created by `new`, empty for other stores
     pub root: PathBuf,
+    store: Box<dyn ConfigStore>,
 }
 
 impl ConfigController {
+    /// controller on json files under root
     pub fn new<P: AsRef<Path>>(root: P) -> anyhow::Result<Self> {
-        create_dir_all(root.as_ref().join("config"))?;
-        Ok(ConfigController {
-            root: root.as_ref().to_path_buf(),
-        })
+        let mut controller = ConfigController::with_store(FileStore::new(root.as_ref())?);
+        controller.root = root.as_ref().to_path_buf();
+        Ok(controller)
     }
 
-    pub fn list_config(&self) -> anyhow::Result<Vec<ConfigList>> {
-        let list = self.config_file_list_path();
-        if !list.exists() {
-            write(&list, "[]")?;
+    pub fn with_store<S: ConfigStore + 'static>(store: S) -> Self {
+        ConfigController {
+            root: PathBuf::new(),
+            store: Box::new(store),
         }
-        let content = read(list)?;
-        Ok(serde_json::from_slice::<Vec<ConfigList>>(&content)?)
+    }
+
+    pub fn list_config(&self) -> anyhow::Result<Vec<ConfigList>> {
+        self.store.list()
+    }
+
+    /// configs matching query, sorted as query specified
+    pub fn query_config(&self, query: &ConfigQuery) -> anyhow::Result<Vec<ConfigList>> {
+        Ok(query.apply(self.list_config()?))
     }
 
     pub fn get_config(&self, id: &str) -> anyhow::Result<DBStruct> {
-        let content = read(self.config_file_path(id))?;
-        Ok(serde_json::from_slice::<DBStruct>(&content)?)
+        self.store.get(id)
+    }
+
+    /// schedule of assessments of saved config
+    pub fn get_schedule(&self, id: &str) -> anyhow::Result<Schedule> {
+        Ok(Schedule::new(&self.get_config(id)?))
+    }
+
+    /// changes from saved config to the struct read from edc building file and ecrf
+    pub fn diff_with_edc<P: AsRef<Path>>(
+        &self,
+        id: &str,
+        kind: &DBKind,
+        edc: P,
+        ecrf: P,
+    ) -> anyhow::Result<Diff> {
+        let saved = self.get_config(id)?;
+        let fresh = db_reader(kind).read(edc, ecrf_reader(kind, ecrf)?)?;
+        Ok(saved.diff(&fresh))
+    }
+
+    /// read struct from new edc building file and ecrf, then carry over corrections of saved config,
+    /// the merged struct is not saved
+    pub fn reimport<P: AsRef<Path>>(
+        &self,
+        id: &str,
+        kind: &DBKind,
+        edc: P,
+        ecrf: P,
+        options: &MergeOptions,
+    ) -> anyhow::Result<Merge> {
+        let saved = self.get_config(id)?;
+        let fresh = db_reader(kind).read(edc, ecrf_reader(kind, ecrf)?)?;
+        Ok(saved.merge(fresh, options))
     }
 
     pub fn save_config(
@@ -39,59 +90,124 @@ impl ConfigController {
         name: &str,
         config: &DBStruct,
     ) -> anyhow::Result<String> {
-        let mut list = self.list_config()?;
-        let id = if let Some(id) = id {
-            id.into()
-        } else {
-            let id = nanoid!();
-            list.push(ConfigList {
+        self.save_config_with_meta(id, name, config, &ConfigMeta::default())
+    }
+
+    /// save config and record its metadata, timestamps are set by controller
+    pub fn save_config_with_meta(
+        &self,
+        id: Option<String>,
+        name: &str,
+        config: &DBStruct,
+        meta: &ConfigMeta,
+    ) -> anyhow::Result<String> {
+        let now = Utc::now();
+        let id = id.unwrap_or_else(|| nanoid!());
+        // entry is updated inside the store lock, so concurrent saves keep each other's changes
+        let update: UpdateEntry = Box::new(|current| {
+            let mut entry = current.unwrap_or(ConfigList {
                 id: id.clone(),
                 name: name.to_string(),
+                study: None,
+                kind: None,
+                sources: vec![],
+                created_at: Some(now),
+                created_by: meta.author.clone(),
+                updated_at: None,
+                updated_by: None,
             });
-            OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(self.config_file_list_path())?
-                .write(&serde_json::to_vec(&list)?)?;
-            id
-        };
-        let content = serde_json::to_vec(config)?;
-        OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(self.config_file_path(&id))?
-            .write(&content)?;
+            entry.name = name.to_string();
+            if meta.study.is_some() {
+                entry.study = meta.study.clone();
+            }
+            if meta.kind.is_some() {
+                entry.kind = meta.kind.clone();
+            }
+            if !meta.sources.is_empty() {
+                entry.sources = meta.sources.clone();
+            }
+            entry.updated_at = Some(now);
+            entry.updated_by = meta.author.clone();
+            Ok(entry)
+        });
+        self.store.save_with(
+            &id,
+            update,
+            config,
+            meta.author.clone(),
+            meta.message.clone(),
+        )?;
         Ok(id)
     }
 
+    /// revisions of config, sorted by revision number
+    pub fn list_revisions(&self, id: &str) -> anyhow::Result<Vec<RevisionInfo>> {
+        self.store.history(id)
+    }
+
+    pub fn get_revision(&self, id: &str, number: usize) -> anyhow::Result<Revision> {
+        self.store.revision(id, number)
+    }
+
+    /// changes from revision `from` to revision `to` of config
+    pub fn diff_revisions(&self, id: &str, from: usize, to: usize) -> anyhow::Result<Diff> {
+        let from = self.get_revision(id, from)?;
+        let to = self.get_revision(id, to)?;
+        Ok(from.config.diff(&to.config))
+    }
+
+    /// save config of an earlier revision as a new revision, return number of the new revision
+    pub fn rollback(
+        &self,
+        id: &str,
+        number: usize,
+        author: Option<String>,
+    ) -> anyhow::Result<usize> {
+        let revision = self.get_revision(id, number)?;
+        let name = revision.info.name;
+        let update: UpdateEntry = Box::new(|current| {
+            let mut entry = current.ok_or(anyhow!("config {} not found", id))?;
+            entry.name = name;
+            entry.updated_at = Some(Utc::now());
+            entry.updated_by = author.clone();
+            Ok(entry)
+        });
+        self.store.save_with(
+            id,
+            update,
+            &revision.config,
+            author.clone(),
+            Some(format!("rollback to revision {}", number)),
+        )
+    }
+
     pub fn remove_config(&self, id: &str) -> anyhow::Result<()> {
-        let mut list = self.list_config()?;
-        list.retain(|x| x.id != id);
-        let list = serde_json::to_vec(&list)?;
-        remove_file(self.config_file_path(id))?;
-        OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(self.config_file_list_path())?
-            .write(&list)?;
-        Ok(())
+        self.store.remove(id)
+    }
+
+    /// upgrade all saved configs to current schema version, data is backed up before upgrading
+    pub fn migrate_all(&self) -> anyhow::Result<Migration> {
+        self.store.migrate_all()
     }
 
-    fn config_file_path(&self, id: &str) -> PathBuf {
-        self.root.join("config").join(format!("{}.json", id))
+    /// restore consistency of store after interrupted writes, such as rebuilding list of file store
+    pub fn repair(&self) -> anyhow::Result<Repair> {
+        self.store.repair()
     }
 
-    fn config_file_list_path(&self) -> PathBuf {
-        self.root.join("config.json")
+    pub(super) fn store(&self) -> &dyn ConfigStore {
+        self.store.as_ref()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::edc::db::{Form, FormVisitBinding, Visit};
+    use crate::{
+        config::store::memory::MemoryStore,
+        edc::db::{tests::db, Form, FormVisitBinding, Visit},
+    };
+    use std::{fs::remove_dir_all, thread};
 
     #[test]
     fn test_config_controller_crud() -> anyhow::Result<()> {
@@ -109,11 +225,19 @@ mod tests {
                     id: 0,
                     name: "v0".to_string(),
                     order: 0,
+                    translation: None,
+                    oid: None,
+                    hidden: false,
+                    imported: None,
                 },
                 Visit {
                     id: 1,
                     name: "v1".to_string(),
                     order: 1,
+                    translation: None,
+                    oid: None,
+                    hidden: false,
+                    imported: None,
                 },
             ],
             form: vec![
@@ -122,12 +246,22 @@ mod tests {
                     name: "f0".to_string(),
                     page: 1,
                     order: 0,
+                    translation: None,
+                    category: None,
+                    oid: None,
+                    hidden: false,
+                    imported: None,
                 },
                 Form {
                     id: 1,
                     name: "f1".to_string(),
                     page: 2,
                     order: 1,
+                    translation: None,
+                    category: None,
+                    oid: None,
+                    hidden: false,
+                    imported: None,
                 },
             ],
             binding: vec![
@@ -169,4 +303,80 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_config_history() -> anyhow::Result<()> {
+        let controller = ConfigController::with_store(MemoryStore::new());
+        assert!(controller.root.as_os_str().is_empty());
+        let mut config = DBStruct {
+            visit: vec![],
+            form: vec![Form {
+                id: 0,
+                name: "f0".to_string(),
+                page: 1,
+                order: 0,
+                translation: None,
+                category: None,
+                oid: None,
+                hidden: false,
+                imported: None,
+            }],
+            binding: vec![],
+        };
+        let meta = ConfigMeta {
+            author: Some("alice".into()),
+            message: Some("initial".into()),
+            ..Default::default()
+        };
+        let id = controller.save_config_with_meta(None, "history", &config, &meta)?;
+        config.form[0].page = 2;
+        controller.save_config(Some(id.clone()), "history", &config)?;
+
+        let revisions = controller.list_revisions(&id)?;
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].author.as_deref(), Some("alice"));
+        assert_eq!(controller.diff_revisions(&id, 1, 2)?.changes.len(), 1);
+
+        assert_eq!(controller.rollback(&id, 1, None)?, 3);
+        assert_eq!(controller.get_config(&id)?.form[0].page, 1);
+        assert_eq!(controller.get_revision(&id, 2)?.config.form[0].page, 2);
+        assert!(controller.get_revision(&id, 4).is_err());
+
+        controller.remove_config(&id)?;
+        assert!(controller.list_revisions(&id)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_save_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        let controller = ConfigController::new(&root)?;
+        assert_eq!(controller.root, root);
+        let config = db();
+        thread::scope(|scope| {
+            for n in 0..8 {
+                let (controller, config) = (&controller, &config);
+                scope.spawn(move || {
+                    let meta = ConfigMeta {
+                        study: (n == 0).then(|| "S1".to_string()),
+                        kind: (n == 1).then_some(DBKind::Rave),
+                        author: Some(format!("user{}", n)),
+                        ..Default::default()
+                    };
+                    controller
+                        .save_config_with_meta(Some("shared".into()), "shared", config, &meta)
+                        .unwrap();
+                });
+            }
+        });
+        let list = controller.list_config()?;
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].study.as_deref(), Some("S1"));
+        assert_eq!(list[0].kind, Some(DBKind::Rave));
+        let revisions = controller.list_revisions("shared")?;
+        assert_eq!(revisions.len(), 8);
+        assert_eq!(list[0].created_by, revisions[0].author);
+        remove_dir_all(root)?;
+        Ok(())
+    }
 }
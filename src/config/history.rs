@@ -0,0 +1,25 @@
+use crate::edc::db::DBStruct;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// one saved state of config, revision files are never modified after written
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    #[serde(flatten)]
+    pub info: RevisionInfo,
+    #[serde(with = "crate::config::schema::versioned")]
+    pub config: DBStruct,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevisionInfo {
+    /// revision number, starts from 1
+    pub number: usize,
+    /// name of config when the revision was saved
+    pub name: String,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,158 @@
+use crate::edc::db::DBStruct;
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+use serde_json::Value;
+
+/// schema version of config documents written by this version
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// migrations of config value, the migration at index `i` upgrades version `i` to `i + 1`
+const MIGRATIONS: &[fn(Value) -> anyhow::Result<Value>] = &[v0_to_v1];
+
+/// saved config document, configs saved before versioning are bare `DBStruct` of version 0
+#[derive(Debug, Serialize)]
+struct Document<'a> {
+    schema: u32,
+    config: &'a DBStruct,
+}
+
+pub fn to_vec(config: &DBStruct) -> anyhow::Result<Vec<u8>> {
+    Ok(serde_json::to_vec(&Document {
+        schema: SCHEMA_VERSION,
+        config,
+    })?)
+}
+
+/// read config document of any supported version
+pub fn from_slice(content: &[u8]) -> anyhow::Result<DBStruct> {
+    from_value(serde_json::from_slice(content)?)
+}
+
+pub fn from_value(document: Value) -> anyhow::Result<DBStruct> {
+    let (_, config) = migrate(document)?;
+    Ok(serde_json::from_value(config)?)
+}
+
+/// upgrade config document to current version, return version of document and upgraded config value
+pub fn migrate(document: Value) -> anyhow::Result<(u32, Value)> {
+    let (version, mut config) = match document {
+        Value::Object(mut object) if object.contains_key("schema") => {
+            let schema = &object["schema"];
+            let version = schema
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or(anyhow!("invalid schema version {} of config", schema))?;
+            let config = object
+                .remove("config")
+                .ok_or(anyhow!("config is missing in document"))?;
+            (version, config)
+        }
+        config => (0, config),
+    };
+    if version > SCHEMA_VERSION {
+        bail!(
+            "config schema version {} is newer than supported version {}",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        config = migration(config)?;
+    }
+    Ok((version, config))
+}
+
+/// serde adapter writing config as versioned document, such as for config in revision
+pub(crate) mod versioned {
+    use super::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(config: &DBStruct, serializer: S) -> Result<S::Ok, S::Error> {
+        Document {
+            schema: SCHEMA_VERSION,
+            config,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DBStruct, D::Error> {
+        from_value(Value::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+/// configs saved before versioning hold only ids, names, pages, orders and bindings, values read
+/// from edc were not recorded for them, so `imported` is set to null and every saved value is taken
+/// as a correction on re-import, forms and visits missing one of those fields are rejected
+fn v0_to_v1(mut config: Value) -> anyhow::Result<Value> {
+    let object = config
+        .as_object_mut()
+        .ok_or(anyhow!("config of version 0 is not an object"))?;
+    let required: [(&str, &[&str]); 2] = [
+        ("visit", &["id", "name", "order"]),
+        ("form", &["id", "name", "page", "order"]),
+    ];
+    for (key, fields) in required {
+        let items = object
+            .get_mut(key)
+            .and_then(Value::as_array_mut)
+            .ok_or(anyhow!("{} of config of version 0 is not a list", key))?;
+        for (index, item) in items.iter_mut().enumerate() {
+            let item = item.as_object_mut().ok_or(anyhow!(
+                "{} {} of config of version 0 is not an object",
+                key,
+                index
+            ))?;
+            if let Some(field) = fields.iter().find(|f| !item.contains_key(**f)) {
+                bail!("{} {} of config of version 0 has no {}", key, index, field);
+            }
+            item.insert("imported".into(), Value::Null);
+        }
+    }
+    object
+        .entry("binding")
+        .or_insert_with(|| Value::Array(vec![]));
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_test() -> anyhow::Result<()> {
+        let legacy = br#"{
+            "visit": [{"id": 0, "name": "V1", "order": 0}],
+            "form": [{"id": 0, "name": "Demographics", "page": 1, "order": 0}],
+            "binding": [{"parent": 0, "children": [0]}]
+        }"#;
+        let (version, config) = migrate(serde_json::from_slice(legacy)?)?;
+        assert_eq!(version, 0);
+        assert_eq!(config["form"][0]["imported"], Value::Null);
+
+        let config = from_slice(legacy)?;
+        assert_eq!(config.form[0].name, "Demographics");
+        let document = to_vec(&config)?;
+        let (version, _) = migrate(serde_json::from_slice(&document)?)?;
+        assert_eq!(version, SCHEMA_VERSION);
+        assert_eq!(from_slice(&document)?.visit[0].name(), "V1");
+
+        let newer = br#"{"schema": 99, "config": {}}"#;
+        assert!(from_slice(newer).is_err());
+        for invalid in ["-1", "1.5", "4294967297"] {
+            let document = format!(r#"{{"schema": {}, "config": {{}}}}"#, invalid);
+            let error = from_slice(document.as_bytes()).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("invalid schema version {} of config", invalid)
+            );
+        }
+
+        let no_page = br#"{"visit": [], "form": [{"id": 0, "name": "DM", "order": 0}]}"#;
+        let error = from_slice(no_page).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "form 0 of config of version 0 has no page"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use super::{
+    config::ConfigList,
+    history::{Revision, RevisionInfo},
+};
+use crate::edc::db::DBStruct;
+use nanoid::nanoid;
+use std::{
+    fs::{rename, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+pub mod file;
+pub mod memory;
+pub mod sqlite;
+
+/// storage of configs, their list entries and revisions
+pub trait ConfigStore: Send + Sync {
+    /// list entries in the order of saving
+    fn list(&self) -> anyhow::Result<Vec<ConfigList>>;
+
+    fn get(&self, id: &str) -> anyhow::Result<DBStruct>;
+
+    /// insert or replace config and its list entry, then record it as a new revision,
+    /// return number of the revision
+    fn save(
+        &self,
+        entry: ConfigList,
+        config: &DBStruct,
+        author: Option<String>,
+        message: Option<String>,
+    ) -> anyhow::Result<usize> {
+        let id = entry.id.clone();
+        self.save_with(&id, Box::new(|_| Ok(entry)), config, author, message)
+    }
+
+    /// like `save`, but the list entry is built from the current entry of `id`, none if config
+    /// is new, under the same lock or transaction as writing, so concurrent saves keep each
+    /// other's changes of the entry
+    fn save_with(
+        &self,
+        id: &str,
+        entry: UpdateEntry<'_>,
+        config: &DBStruct,
+        author: Option<String>,
+        message: Option<String>,
+    ) -> anyhow::Result<usize>;
+
+    /// save configs like `save` one after another, but either all of them are saved or none,
+    /// return numbers of the revisions
+    fn save_all(
+        &self,
+        configs: Vec<(ConfigList, DBStruct)>,
+        author: Option<String>,
+        message: Option<String>,
+    ) -> anyhow::Result<Vec<usize>>;
+
+    /// remove config, its list entry and its revisions
+    fn remove(&self, id: &str) -> anyhow::Result<()>;
+
+    /// revisions of config, sorted by revision number
+    fn history(&self, id: &str) -> anyhow::Result<Vec<RevisionInfo>>;
+
+    fn revision(&self, id: &str, number: usize) -> anyhow::Result<Revision>;
+
+    /// upgrade stored configs to current schema version, stored data is backed up before upgrading
+    fn migrate_all(&self) -> anyhow::Result<Migration>;
+
+    /// restore consistency after interrupted writes, nothing to do for transactional stores
+    fn repair(&self) -> anyhow::Result<Repair> {
+        Ok(Repair::default())
+    }
+}
+
+/// build list entry of config being saved from its current entry
+pub type UpdateEntry<'a> = Box<dyn FnOnce(Option<ConfigList>) -> anyhow::Result<ConfigList> + 'a>;
+
+/// ids of configs changed by repairing config list
+#[derive(Debug, Default)]
+pub struct Repair {
+    /// configs stored without entry in list
+    pub added: Vec<String>,
+    /// entries in list without stored config
+    pub dropped: Vec<String>,
+}
+
+/// result of upgrading stored configs
+#[derive(Debug, Default)]
+pub struct Migration {
+    /// location of backup, none if nothing was upgraded or the store is not persistent
+    pub backup: Option<PathBuf>,
+    /// ids of upgraded configs
+    pub migrated: Vec<String>,
+}
+
+/// suffix of files being written, left behind only if writing is interrupted
+pub(crate) const TEMP_SUFFIX: &str = ".tmp";
+/// suffix of config files being removed, left behind only if removing is interrupted
+pub(crate) const REMOVED_SUFFIX: &str = ".removed";
+
+/// write content to a temp file in the same directory, then rename it to path,
+/// readers see either the old content or the new content but never a partial one
+pub(crate) fn write_atomic<P: AsRef<Path>>(path: P, content: &[u8]) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let temp = write_staged(path, content)?;
+    if let Err(e) = rename(&temp, path) {
+        std::fs::remove_file(&temp).ok();
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// write content to a temp file next to path and return the temp file,
+/// renaming it to path later replaces path at once
+pub(crate) fn write_staged(path: &Path, content: &[u8]) -> anyhow::Result<PathBuf> {
+    let temp = temp_path(path);
+    let result = File::create(&temp).and_then(|mut file| {
+        file.write_all(content)?;
+        file.sync_all()
+    });
+    if let Err(e) = result {
+        std::fs::remove_file(&temp).ok();
+        return Err(e.into());
+    }
+    Ok(temp)
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}{}", name, nanoid!(8), TEMP_SUFFIX))
+}
+
+/// exclusive advisory lock on a lock file, released when dropped
+pub(crate) struct StoreLock {
+    _file: File,
+}
+
+impl StoreLock {
+    /// block until no other process holds the lock
+    pub(crate) fn acquire<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        file.lock()?;
+        Ok(StoreLock { _file: file })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// config saved before versioning, a bare struct without the fields added since
+    pub(crate) const V0_CONFIG: &str = r#"{
+        "visit": [{"id": 0, "name": "V1", "order": 0}],
+        "form": [{"id": 0, "name": "Demographics", "page": 1, "order": 0}],
+        "binding": [{"parent": 0, "children": [0]}]
+    }"#;
+
+    pub(crate) fn entry(id: &str, name: &str) -> ConfigList {
+        ConfigList {
+            id: id.into(),
+            name: name.into(),
+            study: None,
+            kind: None,
+            sources: vec![],
+            created_at: None,
+            created_by: None,
+            updated_at: None,
+            updated_by: None,
+        }
+    }
+
+    /// behaviour shared by all stores
+    pub(crate) fn check_store(store: &dyn ConfigStore) -> anyhow::Result<()> {
+        let mut config = DBStruct {
+            visit: vec![],
+            form: vec![],
+            binding: vec![],
+        };
+        assert!(store.list()?.is_empty());
+        assert_eq!(store.save(entry("b", "B"), &config, None, None)?, 1);
+        assert_eq!(store.save(entry("a", "A"), &config, None, None)?, 1);
+        config.binding.push(crate::edc::db::FormVisitBinding {
+            parent: 0,
+            children: vec![0],
+        });
+        let number = store.save(entry("b", "B2"), &config, Some("alice".into()), None)?;
+        assert_eq!(number, 2);
+
+        let list = store.list()?;
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].name, "B2");
+        assert_eq!(list[1].id, "a");
+        assert_eq!(store.get("b")?.binding.len(), 1);
+        assert!(store.get("c").is_err());
+
+        let history = store.history("b")?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].author.as_deref(), Some("alice"));
+        assert!(store.revision("b", 1)?.config.binding.is_empty());
+        assert!(store.revision("b", 3).is_err());
+
+        store.remove("b")?;
+        assert_eq!(store.list()?.len(), 1);
+        assert!(store.get("b").is_err());
+        assert!(store.history("b")?.is_empty());
+
+        let numbers = store.save_all(
+            vec![
+                (entry("a", "A2"), config.clone()),
+                (entry("c", "C"), config.clone()),
+            ],
+            None,
+            Some("batch".into()),
+        )?;
+        assert_eq!(numbers, vec![2, 1]);
+        let list = store.list()?;
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].name, "A2");
+        assert_eq!(list[1].id, "c");
+        assert_eq!(store.get("c")?.binding.len(), 1);
+        assert_eq!(store.history("c")?[0].message.as_deref(), Some("batch"));
+        Ok(())
+    }
+}
@@ -0,0 +1,551 @@
+use super::{
+    write_atomic, write_staged, ConfigStore, Migration, Repair, StoreLock, UpdateEntry,
+    REMOVED_SUFFIX, TEMP_SUFFIX,
+};
+use crate::{
+    config::{
+        config::ConfigList,
+        history::{Revision, RevisionInfo},
+        schema::{self, SCHEMA_VERSION},
+    },
+    edc::db::DBStruct,
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use std::{
+    collections::HashSet,
+    fs::{copy, create_dir_all, read, read_dir, remove_dir_all, remove_file, rename},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// json files under root, `config.json` for list, `config/<id>.json` for configs
+/// and `history/<id>/<number>.json` for revisions
+pub struct FileStore {
+    pub root: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P: AsRef<Path>>(root: P) -> anyhow::Result<Self> {
+        create_dir_all(root.as_ref().join("config"))?;
+        Ok(FileStore {
+            root: root.as_ref().to_path_buf(),
+        })
+    }
+
+    fn read_list(&self) -> anyhow::Result<Vec<ConfigList>> {
+        let list = self.config_file_list_path();
+        if !list.exists() {
+            write_atomic(&list, b"[]")?;
+        }
+        let content = read(list)?;
+        Ok(serde_json::from_slice::<Vec<ConfigList>>(&content)?)
+    }
+
+    fn write_revision(
+        &self,
+        id: &str,
+        name: &str,
+        config: &DBStruct,
+        author: Option<String>,
+        message: Option<String>,
+    ) -> anyhow::Result<usize> {
+        create_dir_all(self.history_dir(id))?;
+        let number = self
+            .revision_numbers(id)?
+            .into_iter()
+            .max()
+            .unwrap_or_default()
+            + 1;
+        let revision = Revision {
+            info: RevisionInfo {
+                number,
+                name: name.into(),
+                author,
+                message,
+                created_at: Utc::now(),
+            },
+            config: config.clone(),
+        };
+        // existing revisions are never overwritten
+        let path = self.revision_file_path(id, number);
+        if path.exists() {
+            return Err(anyhow!(
+                "revision {} of config {} already exists",
+                number,
+                id
+            ));
+        }
+        write_atomic(path, &serde_json::to_vec(&revision)?)?;
+        Ok(number)
+    }
+
+    fn revision_numbers(&self, id: &str) -> anyhow::Result<Vec<usize>> {
+        let dir = self.history_dir(id);
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut numbers = vec![];
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if !path.extension().is_some_and(|e| e.eq("json")) {
+                continue;
+            }
+            if let Some(number) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<usize>().ok())
+            {
+                numbers.push(number);
+            }
+        }
+        Ok(numbers)
+    }
+
+    /// write revisions and staged config files of configs, return numbers of the revisions
+    fn stage_all(
+        &self,
+        configs: &[(ConfigList, DBStruct)],
+        author: Option<String>,
+        message: Option<String>,
+        written: &mut Vec<(PathBuf, Option<PathBuf>)>,
+    ) -> anyhow::Result<Vec<usize>> {
+        let mut numbers = Vec::with_capacity(configs.len());
+        for (entry, config) in configs {
+            let number = self.write_revision(
+                &entry.id,
+                &entry.name,
+                config,
+                author.clone(),
+                message.clone(),
+            )?;
+            written.push((self.revision_file_path(&entry.id, number), None));
+            numbers.push(number);
+            let path = self.config_file_path(&entry.id);
+            written.push((write_staged(&path, &schema::to_vec(config)?)?, Some(path)));
+        }
+        Ok(numbers)
+    }
+
+    /// remove files left by interrupted writes in directory, not recursive
+    fn remove_temp_files(&self, dir: &Path) -> anyhow::Result<()> {
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.to_string_lossy().ends_with(TEMP_SUFFIX) {
+                remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> anyhow::Result<StoreLock> {
+        StoreLock::acquire(self.root.join("config.lock"))
+    }
+
+    fn config_file_path(&self, id: &str) -> PathBuf {
+        self.root.join("config").join(format!("{}.json", id))
+    }
+
+    fn removed_file_path(&self, id: &str) -> PathBuf {
+        self.root
+            .join("config")
+            .join(format!("{}.json{}", id, REMOVED_SUFFIX))
+    }
+
+    fn history_dir(&self, id: &str) -> PathBuf {
+        self.root.join("history").join(id)
+    }
+
+    fn revision_file_path(&self, id: &str, number: usize) -> PathBuf {
+        self.history_dir(id).join(format!("{}.json", number))
+    }
+
+    fn config_file_list_path(&self) -> PathBuf {
+        self.root.join("config.json")
+    }
+}
+
+impl ConfigStore for FileStore {
+    fn list(&self) -> anyhow::Result<Vec<ConfigList>> {
+        self.read_list()
+    }
+
+    fn get(&self, id: &str) -> anyhow::Result<DBStruct> {
+        schema::from_slice(&read(self.config_file_path(id))?)
+    }
+
+    fn save_with(
+        &self,
+        id: &str,
+        entry: UpdateEntry<'_>,
+        config: &DBStruct,
+        author: Option<String>,
+        message: Option<String>,
+    ) -> anyhow::Result<usize> {
+        let _lock = self.lock()?;
+        let mut list = self.read_list()?;
+        let index = list.iter().position(|c| c.id.eq(id));
+        let entry = entry(index.map(|i| list[i].clone()))?;
+        let number = self.write_revision(id, &entry.name, config, author, message)?;
+        match index {
+            Some(index) => list[index] = entry,
+            None => list.push(entry),
+        }
+        // config file is written before list, so an interrupted save never leaves a dangling entry
+        write_atomic(self.config_file_path(id), &schema::to_vec(config)?)?;
+        write_atomic(self.config_file_list_path(), &serde_json::to_vec(&list)?)?;
+        Ok(number)
+    }
+
+    /// revisions and config files are staged first and removed again if any of them fails,
+    /// staged config files are then moved into place and list is written once
+    fn save_all(
+        &self,
+        configs: Vec<(ConfigList, DBStruct)>,
+        author: Option<String>,
+        message: Option<String>,
+    ) -> anyhow::Result<Vec<usize>> {
+        let _lock = self.lock()?;
+        let mut list = self.read_list()?;
+        // written files as (file, destination of staged file), revisions are written in place
+        let mut written = vec![];
+        let numbers = match self.stage_all(&configs, author, message, &mut written) {
+            Ok(numbers) => numbers,
+            Err(e) => {
+                for (file, _) in written {
+                    remove_file(file).ok();
+                }
+                return Err(e);
+            }
+        };
+
+        for (staged, path) in written {
+            if let Some(path) = path {
+                rename(staged, path)?;
+            }
+        }
+        for (entry, _) in configs {
+            match list.iter().position(|c| c.id.eq(&entry.id)) {
+                Some(index) => list[index] = entry,
+                None => list.push(entry),
+            }
+        }
+        // config files are moved before list is written, so an interrupted save never leaves a dangling entry
+        write_atomic(self.config_file_list_path(), &serde_json::to_vec(&list)?)?;
+        Ok(numbers)
+    }
+
+    fn remove(&self, id: &str) -> anyhow::Result<()> {
+        let _lock = self.lock()?;
+        let mut list = self.read_list()?;
+        let len = list.len();
+        list.retain(|x| x.id != id);
+        // config file is moved aside first, so an interrupted remove never brings the config back in repair
+        let removed = self.removed_file_path(id);
+        match rename(self.config_file_path(id), &removed) {
+            Ok(()) => {}
+            // dangling entry whose file is gone is still removed from list
+            Err(e) if e.kind().eq(&ErrorKind::NotFound) && list.len() < len => {}
+            Err(e) if e.kind().eq(&ErrorKind::NotFound) => {
+                return Err(anyhow!("config {} not found", id))
+            }
+            Err(e) => return Err(e.into()),
+        }
+        write_atomic(self.config_file_list_path(), &serde_json::to_vec(&list)?)?;
+        if removed.exists() {
+            remove_file(removed)?;
+        }
+        let history = self.history_dir(id);
+        if history.exists() {
+            remove_dir_all(history)?;
+        }
+        Ok(())
+    }
+
+    fn history(&self, id: &str) -> anyhow::Result<Vec<RevisionInfo>> {
+        let mut revisions = self
+            .revision_numbers(id)?
+            .into_iter()
+            .map(|number| Ok(self.revision(id, number)?.info))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        revisions.sort_by_key(|r| r.number);
+        Ok(revisions)
+    }
+
+    fn revision(&self, id: &str, number: usize) -> anyhow::Result<Revision> {
+        let path = self.revision_file_path(id, number);
+        if !path.exists() {
+            return Err(anyhow!("revision {} of config {} not found", number, id));
+        }
+        Ok(serde_json::from_slice::<Revision>(&read(path)?)?)
+    }
+
+    /// configs are copied into `backup/<time>` under root before being rewritten,
+    /// revisions are left as is since they are upgraded when read
+    fn migrate_all(&self) -> anyhow::Result<Migration> {
+        let _lock = self.lock()?;
+        let mut upgraded = vec![];
+        for entry in self.read_list()? {
+            let path = self.config_file_path(&entry.id);
+            let (version, config) = schema::migrate(serde_json::from_slice(&read(&path)?)?)?;
+            if version < SCHEMA_VERSION {
+                upgraded.push((entry.id, serde_json::from_value::<DBStruct>(config)?));
+            }
+        }
+        if upgraded.is_empty() {
+            return Ok(Migration::default());
+        }
+
+        let backup = self
+            .root
+            .join("backup")
+            .join(Utc::now().format("%Y%m%dT%H%M%S%.3f").to_string());
+        create_dir_all(backup.join("config"))?;
+        copy(self.config_file_list_path(), backup.join("config.json"))?;
+        for (id, _) in upgraded.iter() {
+            copy(
+                self.config_file_path(id),
+                backup.join("config").join(format!("{}.json", id)),
+            )?;
+        }
+
+        let mut migration = Migration {
+            backup: Some(backup),
+            migrated: Vec::with_capacity(upgraded.len()),
+        };
+        for (id, config) in upgraded {
+            write_atomic(self.config_file_path(&id), &schema::to_vec(&config)?)?;
+            migration.migrated.push(id);
+        }
+        Ok(migration)
+    }
+
+    /// rebuild config list from config files on disk, such as after an interrupted write,
+    /// entries whose file is missing are dropped and files without entry are added
+    fn repair(&self) -> anyhow::Result<Repair> {
+        let _lock = self.lock()?;
+        let list = read(self.config_file_list_path())
+            .ok()
+            .and_then(|content| serde_json::from_slice::<Vec<ConfigList>>(&content).ok())
+            .unwrap_or_default();
+
+        let mut ids = vec![];
+        for entry in read_dir(self.root.join("config"))? {
+            let path = entry?.path();
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if let Some(id) = name
+                .strip_suffix(REMOVED_SUFFIX)
+                .and_then(|n| n.strip_suffix(".json"))
+            {
+                remove_file(&path)?;
+                let history = self.history_dir(id);
+                if history.exists() {
+                    remove_dir_all(history)?;
+                }
+                continue;
+            }
+            if name.ends_with(TEMP_SUFFIX) {
+                remove_file(&path)?;
+                continue;
+            }
+            if let Some(id) = name.strip_suffix(".json") {
+                ids.push(id.to_string());
+            }
+        }
+        self.remove_temp_files(&self.root)?;
+        let history = self.root.join("history");
+        if history.exists() {
+            for entry in read_dir(history)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    self.remove_temp_files(&path)?;
+                }
+            }
+        }
+
+        let mut repair = Repair::default();
+        let mut seen = HashSet::with_capacity(ids.len());
+        let mut repaired = Vec::with_capacity(ids.len());
+        for entry in list {
+            if !ids.contains(&entry.id) {
+                repair.dropped.push(entry.id);
+            } else if seen.insert(entry.id.clone()) {
+                repaired.push(entry);
+            }
+        }
+        ids.sort();
+        for id in ids {
+            if seen.contains(&id) {
+                continue;
+            }
+            let latest = self
+                .revision_numbers(&id)?
+                .into_iter()
+                .max()
+                .and_then(|number| self.revision(&id, number).ok());
+            repaired.push(ConfigList {
+                id: id.clone(),
+                name: latest
+                    .as_ref()
+                    .map(|r| r.info.name.clone())
+                    .unwrap_or(id.clone()),
+                study: None,
+                kind: None,
+                sources: vec![],
+                created_at: None,
+                created_by: None,
+                updated_at: latest.as_ref().map(|r| r.info.created_at),
+                updated_by: latest.and_then(|r| r.info.author),
+            });
+            repair.added.push(id);
+        }
+        write_atomic(
+            self.config_file_list_path(),
+            &serde_json::to_vec(&repaired)?,
+        )?;
+        Ok(repair)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::store::tests::{check_store, entry, V0_CONFIG};
+    use nanoid::nanoid;
+
+    #[test]
+    fn file_store_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        check_store(&FileStore::new(&root)?)?;
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn repair_interrupted_writes_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        let store = FileStore::new(&root)?;
+        let config = DBStruct {
+            visit: vec![],
+            form: vec![],
+            binding: vec![],
+        };
+        store.save(entry("kept", "kept"), &config, None, None)?;
+        store.save(entry("removed", "removed"), &config, None, None)?;
+
+        // save interrupted after writing config file, before writing list
+        store.save(entry("orphan", "orphan"), &config, None, None)?;
+        let list = store
+            .list()?
+            .into_iter()
+            .filter(|c| c.id.ne("orphan"))
+            .collect::<Vec<_>>();
+        write_atomic(store.config_file_list_path(), &serde_json::to_vec(&list)?)?;
+        // remove interrupted after moving config file aside, before writing list
+        rename(
+            store.config_file_path("removed"),
+            store.removed_file_path("removed"),
+        )?;
+        // write interrupted before renaming temp file, leaving list truncated
+        std::fs::write(root.join(".config.json.abc.tmp"), "[]")?;
+        std::fs::write(store.config_file_list_path(), r#"[{"id":"#)?;
+        assert!(store.list().is_err());
+
+        let repair = store.repair()?;
+        assert_eq!(repair.added, vec!["kept", "orphan"]);
+        let list = store.list()?;
+        assert_eq!(list.len(), 2);
+        assert!(list
+            .iter()
+            .any(|c| c.id.eq("orphan") && c.name.eq("orphan")));
+        assert!(!root.join(".config.json.abc.tmp").exists());
+        assert!(!store.removed_file_path("removed").exists());
+        assert!(store.history("removed")?.is_empty());
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn remove_dangling_entry_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        let store = FileStore::new(&root)?;
+        let config = DBStruct {
+            visit: vec![],
+            form: vec![],
+            binding: vec![],
+        };
+        store.save(entry("dangling", "dangling"), &config, None, None)?;
+        remove_file(store.config_file_path("dangling"))?;
+        store.remove("dangling")?;
+        assert!(store.list()?.is_empty());
+        assert!(store.remove("dangling").is_err());
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn save_all_failed_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        let store = FileStore::new(&root)?;
+        let config = DBStruct {
+            visit: vec![],
+            form: vec![],
+            binding: vec![],
+        };
+        store.save(entry("kept", "kept"), &config, None, None)?;
+        // history of the second config can not be created, after the first one is staged
+        create_dir_all(root.join("history"))?;
+        std::fs::write(store.history_dir("blocked"), "")?;
+        let configs = vec![
+            (entry("kept", "renamed"), config.clone()),
+            (entry("blocked", "blocked"), config.clone()),
+        ];
+        assert!(store.save_all(configs, None, None).is_err());
+
+        let list = store.list()?;
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].name, "kept");
+        assert_eq!(store.history("kept")?.len(), 1);
+        // neither staged config files nor the config file of the failed one are left
+        assert_eq!(read_dir(root.join("config"))?.count(), 1);
+        remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_all_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        let store = FileStore::new(&root)?;
+        let config = DBStruct {
+            visit: vec![],
+            form: vec![],
+            binding: vec![],
+        };
+        store.save(entry("current", "current"), &config, None, None)?;
+        // config saved before versioning
+        store.save(entry("legacy", "legacy"), &config, None, None)?;
+        std::fs::write(store.config_file_path("legacy"), V0_CONFIG)?;
+        assert_eq!(store.get("legacy")?.visit[0].name(), "V1");
+
+        let migration = store.migrate_all()?;
+        assert_eq!(migration.migrated, vec!["legacy"]);
+        let backup = migration.backup.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(backup.join("config").join("legacy.json"))?,
+            V0_CONFIG
+        );
+        let (version, _) = schema::migrate(serde_json::from_slice(&read(
+            store.config_file_path("legacy"),
+        )?)?)?;
+        assert_eq!(version, SCHEMA_VERSION);
+        let migrated = store.get("legacy")?;
+        assert_eq!(migrated.form[0].page, 1);
+        assert!(migrated.form[0].imported.is_none());
+        assert!(store.migrate_all()?.migrated.is_empty());
+        remove_dir_all(root)?;
+        Ok(())
+    }
+}
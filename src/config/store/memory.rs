@@ -0,0 +1,151 @@
+use super::{ConfigStore, Migration, UpdateEntry};
+use crate::{
+    config::{
+        config::ConfigList,
+        history::{Revision, RevisionInfo},
+    },
+    edc::db::DBStruct,
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use std::{collections::HashMap, sync::Mutex};
+
+/// configs kept in memory only, such as for tests
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    inner: Mutex<Memory>,
+}
+
+#[derive(Debug, Default)]
+struct Memory {
+    list: Vec<ConfigList>,
+    configs: HashMap<String, DBStruct>,
+    history: HashMap<String, Vec<Revision>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    fn memory(&self) -> anyhow::Result<std::sync::MutexGuard<'_, Memory>> {
+        self.inner
+            .lock()
+            .map_err(|_| anyhow!("memory store is poisoned"))
+    }
+}
+
+impl Memory {
+    /// replace config and its entry, return number of the new revision
+    fn insert(
+        &mut self,
+        entry: ConfigList,
+        config: &DBStruct,
+        author: Option<String>,
+        message: Option<String>,
+    ) -> usize {
+        let history = self.history.entry(entry.id.clone()).or_default();
+        let number = history.len() + 1;
+        history.push(Revision {
+            info: RevisionInfo {
+                number,
+                name: entry.name.clone(),
+                author,
+                message,
+                created_at: Utc::now(),
+            },
+            config: config.clone(),
+        });
+        self.configs.insert(entry.id.clone(), config.clone());
+        match self.list.iter().position(|c| c.id.eq(&entry.id)) {
+            Some(index) => self.list[index] = entry,
+            None => self.list.push(entry),
+        }
+        number
+    }
+}
+
+impl ConfigStore for MemoryStore {
+    fn list(&self) -> anyhow::Result<Vec<ConfigList>> {
+        Ok(self.memory()?.list.clone())
+    }
+
+    fn get(&self, id: &str) -> anyhow::Result<DBStruct> {
+        self.memory()?
+            .configs
+            .get(id)
+            .cloned()
+            .ok_or(anyhow!("config {} not found", id))
+    }
+
+    fn save_with(
+        &self,
+        id: &str,
+        entry: UpdateEntry<'_>,
+        config: &DBStruct,
+        author: Option<String>,
+        message: Option<String>,
+    ) -> anyhow::Result<usize> {
+        let mut memory = self.memory()?;
+        let current = memory.list.iter().find(|c| c.id.eq(id)).cloned();
+        let entry = entry(current)?;
+        Ok(memory.insert(entry, config, author, message))
+    }
+
+    fn save_all(
+        &self,
+        configs: Vec<(ConfigList, DBStruct)>,
+        author: Option<String>,
+        message: Option<String>,
+    ) -> anyhow::Result<Vec<usize>> {
+        let mut memory = self.memory()?;
+        Ok(configs
+            .into_iter()
+            .map(|(entry, config)| memory.insert(entry, &config, author.clone(), message.clone()))
+            .collect())
+    }
+
+    fn remove(&self, id: &str) -> anyhow::Result<()> {
+        let mut memory = self.memory()?;
+        if memory.configs.remove(id).is_none() {
+            return Err(anyhow!("config {} not found", id));
+        }
+        memory.list.retain(|c| c.id != id);
+        memory.history.remove(id);
+        Ok(())
+    }
+
+    fn history(&self, id: &str) -> anyhow::Result<Vec<RevisionInfo>> {
+        Ok(self
+            .memory()?
+            .history
+            .get(id)
+            .map(|revisions| revisions.iter().map(|r| r.info.clone()).collect())
+            .unwrap_or_default())
+    }
+
+    /// configs in memory are always of current version
+    fn migrate_all(&self) -> anyhow::Result<Migration> {
+        Ok(Migration::default())
+    }
+
+    fn revision(&self, id: &str, number: usize) -> anyhow::Result<Revision> {
+        self.memory()?
+            .history
+            .get(id)
+            .and_then(|revisions| revisions.iter().find(|r| r.info.number.eq(&number)))
+            .cloned()
+            .ok_or(anyhow!("revision {} of config {} not found", number, id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::store::tests::check_store;
+
+    #[test]
+    fn memory_store_test() -> anyhow::Result<()> {
+        check_store(&MemoryStore::new())
+    }
+}
@@ -0,0 +1,322 @@
+use super::{ConfigStore, Migration, UpdateEntry};
+use crate::{
+    config::{
+        config::ConfigList,
+        history::{Revision, RevisionInfo},
+        schema::{self, SCHEMA_VERSION},
+    },
+    edc::db::DBStruct,
+};
+use anyhow::anyhow;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+const SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS config (
+    id TEXT PRIMARY KEY,
+    seq INTEGER NOT NULL,
+    entry TEXT NOT NULL,
+    content TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS revision (
+    config_id TEXT NOT NULL,
+    number INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    PRIMARY KEY (config_id, number)
+);
+"#;
+
+/// configs in an embedded sqlite database, list entries and revisions are stored as json
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+    /// path of database file, none for in-memory database
+    path: Option<PathBuf>,
+}
+
+impl SqliteStore {
+    pub fn open<P: AsRef<Path>>(p: P) -> anyhow::Result<Self> {
+        SqliteStore::init(
+            Connection::open(p.as_ref())?,
+            Some(p.as_ref().to_path_buf()),
+        )
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        SqliteStore::init(Connection::open_in_memory()?, None)
+    }
+
+    fn init(conn: Connection, path: Option<PathBuf>) -> anyhow::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+            path,
+        })
+    }
+
+    fn conn(&self) -> anyhow::Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| anyhow!("sqlite store is poisoned"))
+    }
+}
+
+impl ConfigStore for SqliteStore {
+    fn list(&self) -> anyhow::Result<Vec<ConfigList>> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare("SELECT entry FROM config ORDER BY seq")?;
+        let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+        let mut list = vec![];
+        for entry in rows {
+            list.push(serde_json::from_str::<ConfigList>(&entry?)?);
+        }
+        Ok(list)
+    }
+
+    fn get(&self, id: &str) -> anyhow::Result<DBStruct> {
+        let content = self
+            .conn()?
+            .query_row(
+                "SELECT content FROM config WHERE id = ?1",
+                params![id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .ok_or(anyhow!("config {} not found", id))?;
+        schema::from_slice(content.as_bytes())
+    }
+
+    fn save_with(
+        &self,
+        id: &str,
+        entry: UpdateEntry<'_>,
+        config: &DBStruct,
+        author: Option<String>,
+        message: Option<String>,
+    ) -> anyhow::Result<usize> {
+        let mut conn = self.conn()?;
+        // take the write lock before reading entry, so other processes can not update it meanwhile
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let number = save_in(&tx, id, entry, config, author, message)?;
+        tx.commit()?;
+        Ok(number)
+    }
+
+    fn save_all(
+        &self,
+        configs: Vec<(ConfigList, DBStruct)>,
+        author: Option<String>,
+        message: Option<String>,
+    ) -> anyhow::Result<Vec<usize>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut numbers = Vec::with_capacity(configs.len());
+        for (entry, config) in configs {
+            let id = entry.id.clone();
+            numbers.push(save_in(
+                &tx,
+                &id,
+                Box::new(|_| Ok(entry)),
+                &config,
+                author.clone(),
+                message.clone(),
+            )?);
+        }
+        // dropping the transaction without commit rolls back configs saved before an error
+        tx.commit()?;
+        Ok(numbers)
+    }
+
+    fn remove(&self, id: &str) -> anyhow::Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        if tx.execute("DELETE FROM config WHERE id = ?1", params![id])? == 0 {
+            return Err(anyhow!("config {} not found", id));
+        }
+        tx.execute("DELETE FROM revision WHERE config_id = ?1", params![id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn history(&self, id: &str) -> anyhow::Result<Vec<RevisionInfo>> {
+        let conn = self.conn()?;
+        let mut statement =
+            conn.prepare("SELECT content FROM revision WHERE config_id = ?1 ORDER BY number")?;
+        let rows = statement.query_map(params![id], |row| row.get::<_, String>(0))?;
+        let mut revisions = vec![];
+        for content in rows {
+            revisions.push(serde_json::from_str::<Revision>(&content?)?.info);
+        }
+        Ok(revisions)
+    }
+
+    /// database is copied next to its file before configs are rewritten,
+    /// revisions are left as is since they are upgraded when read
+    fn migrate_all(&self) -> anyhow::Result<Migration> {
+        let mut conn = self.conn()?;
+        let mut upgraded = vec![];
+        {
+            let mut statement = conn.prepare("SELECT id, content FROM config ORDER BY seq")?;
+            let rows = statement.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            for row in rows {
+                let (id, content) = row?;
+                let (version, config) = schema::migrate(serde_json::from_str(&content)?)?;
+                if version < SCHEMA_VERSION {
+                    upgraded.push((id, serde_json::from_value::<DBStruct>(config)?));
+                }
+            }
+        }
+        if upgraded.is_empty() {
+            return Ok(Migration::default());
+        }
+
+        let backup = self.path.as_ref().map(|path| {
+            let mut name = path.as_os_str().to_owned();
+            name.push(format!(".{}.bak", Utc::now().format("%Y%m%dT%H%M%S%.3f")));
+            PathBuf::from(name)
+        });
+        if let Some(backup) = backup.as_ref() {
+            conn.execute("VACUUM INTO ?1", params![backup.to_string_lossy()])?;
+        }
+
+        let tx = conn.transaction()?;
+        let mut migration = Migration {
+            backup,
+            migrated: Vec::with_capacity(upgraded.len()),
+        };
+        for (id, config) in upgraded {
+            tx.execute(
+                "UPDATE config SET content = ?2 WHERE id = ?1",
+                params![id, String::from_utf8(schema::to_vec(&config)?)?],
+            )?;
+            migration.migrated.push(id);
+        }
+        tx.commit()?;
+        Ok(migration)
+    }
+
+    fn revision(&self, id: &str, number: usize) -> anyhow::Result<Revision> {
+        let content = self
+            .conn()?
+            .query_row(
+                "SELECT content FROM revision WHERE config_id = ?1 AND number = ?2",
+                params![id, number as i64],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .ok_or(anyhow!("revision {} of config {} not found", number, id))?;
+        Ok(serde_json::from_str::<Revision>(&content)?)
+    }
+}
+
+/// save config within transaction, return number of the new revision
+fn save_in(
+    tx: &Transaction<'_>,
+    id: &str,
+    entry: UpdateEntry<'_>,
+    config: &DBStruct,
+    author: Option<String>,
+    message: Option<String>,
+) -> anyhow::Result<usize> {
+    let current = tx
+        .query_row(
+            "SELECT entry FROM config WHERE id = ?1",
+            params![id],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?
+        .map(|entry| serde_json::from_str::<ConfigList>(&entry))
+        .transpose()?;
+    let entry = entry(current)?;
+    let number = tx.query_row(
+        "SELECT COALESCE(MAX(number), 0) + 1 FROM revision WHERE config_id = ?1",
+        params![id],
+        |row| row.get::<_, i64>(0),
+    )? as usize;
+    let revision = Revision {
+        info: RevisionInfo {
+            number,
+            name: entry.name.clone(),
+            author,
+            message,
+            created_at: Utc::now(),
+        },
+        config: config.clone(),
+    };
+    tx.execute(
+        "INSERT INTO revision (config_id, number, content) VALUES (?1, ?2, ?3)",
+        params![id, number as i64, serde_json::to_string(&revision)?],
+    )?;
+    tx.execute(
+        "INSERT INTO config (id, seq, entry, content)
+         VALUES (?1, (SELECT COALESCE(MAX(seq), 0) + 1 FROM config), ?2, ?3)
+         ON CONFLICT(id) DO UPDATE SET entry = excluded.entry, content = excluded.content",
+        params![
+            id,
+            serde_json::to_string(&entry)?,
+            String::from_utf8(schema::to_vec(config)?)?
+        ],
+    )?;
+    Ok(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::store::tests::{check_store, entry, V0_CONFIG};
+    use nanoid::nanoid;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    #[test]
+    fn sqlite_store_test() -> anyhow::Result<()> {
+        check_store(&SqliteStore::open_in_memory()?)
+    }
+
+    #[test]
+    fn migrate_all_test() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(nanoid!());
+        create_dir_all(&root)?;
+        let store = SqliteStore::open(root.join("config.db"))?;
+        let config = DBStruct {
+            visit: vec![],
+            form: vec![],
+            binding: vec![],
+        };
+        store.save(entry("current", "current"), &config, None, None)?;
+        // config saved before versioning
+        store.save(entry("legacy", "legacy"), &config, None, None)?;
+        store.conn()?.execute(
+            "UPDATE config SET content = ?2 WHERE id = ?1",
+            params!["legacy", V0_CONFIG],
+        )?;
+        assert_eq!(store.get("legacy")?.visit[0].name(), "V1");
+
+        let migration = store.migrate_all()?;
+        assert_eq!(migration.migrated, vec!["legacy"]);
+        let backup = Connection::open(migration.backup.unwrap())?.query_row(
+            "SELECT content FROM config WHERE id = 'legacy'",
+            [],
+            |row| row.get::<_, String>(0),
+        )?;
+        assert_eq!(backup, V0_CONFIG);
+        let content = store.conn()?.query_row(
+            "SELECT content FROM config WHERE id = 'legacy'",
+            [],
+            |row| row.get::<_, String>(0),
+        )?;
+        let (version, _) = schema::migrate(serde_json::from_str(&content)?)?;
+        assert_eq!(version, SCHEMA_VERSION);
+        let migrated = store.get("legacy")?;
+        assert_eq!(migrated.form[0].page, 1);
+        assert!(migrated.form[0].imported.is_none());
+        assert!(store.migrate_all()?.migrated.is_empty());
+        drop(store);
+        remove_dir_all(root)?;
+        Ok(())
+    }
+}
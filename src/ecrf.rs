@@ -1,9 +1,23 @@
 use crate::edc::{db::DBKind, ecollect::ecrf::ECollectECRF, rave::ecrf::RaveECRF};
-use std::path::Path;
+use std::{ops::RangeInclusive, path::Path};
 
 pub trait ECRF {
     fn form_page(&self, form: &str) -> Option<usize>;
     fn list_forms(&self) -> Vec<String>;
+
+    /// pages of form, from its first page to the page before the next form or the last page of ecrf
+    fn form_pages(&self, form: &str, page_count: usize) -> Option<RangeInclusive<usize>> {
+        let start = self.form_page(form)?;
+        let end = self
+            .list_forms()
+            .iter()
+            .filter_map(|f| self.form_page(f))
+            .filter(|page| *page > start)
+            .min()
+            .map(|page| page - 1)
+            .unwrap_or(page_count);
+        Some(start..=end.max(start))
+    }
 }
 
 pub fn ecrf_reader<P: AsRef<Path>>(kind: &DBKind, file: P) -> anyhow::Result<Box<dyn ECRF>> {
@@ -1,3 +1,9 @@
+pub mod category;
 pub mod db;
+pub mod diff;
 pub mod ecollect;
+pub mod edit;
+pub mod merge;
 pub mod rave;
+pub mod schedule;
+pub mod validate;
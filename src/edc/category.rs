@@ -0,0 +1,47 @@
+use calamine::{open_workbook, Data, DataType, Range, Reader, Xlsx};
+use std::{collections::HashMap, path::Path};
+
+/// read user mapping of form categories from the first sheet of workbook, return HashMap<form name, category>
+pub fn read_category_mapping<P: AsRef<Path>>(p: P) -> anyhow::Result<HashMap<String, String>> {
+    let mut workbook: Xlsx<_> = open_workbook(p)?;
+    let sheet = workbook
+        .worksheet_range_at(0)
+        .unwrap_or(Ok(Range::default()))?;
+    Ok(category_mapping(&sheet))
+}
+
+/// first row is header, column A is form name and column B is category
+pub(crate) fn category_mapping(sheet: &Range<Data>) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    for row in sheet.rows().skip(1) {
+        let form = row.first().and_then(|c| c.as_string());
+        let category = row.get(1).and_then(|c| c.as_string());
+        if let (Some(form), Some(category)) = (form, category) {
+            let (form, category) = (form.trim(), category.trim());
+            if !form.is_empty() && !category.is_empty() {
+                map.insert(form.to_string(), category.to_string());
+            }
+        }
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn category_mapping_test() {
+        let mut sheet = Range::new((0, 0), (3, 1));
+        sheet.set_value((0, 0), Data::String("Form".into()));
+        sheet.set_value((0, 1), Data::String("Category".into()));
+        sheet.set_value((1, 0), Data::String("血常规".into()));
+        sheet.set_value((1, 1), Data::String("Labs".into()));
+        sheet.set_value((2, 0), Data::String("生命体征".into()));
+        sheet.set_value((3, 0), Data::String("血生化 ".into()));
+        sheet.set_value((3, 1), Data::String("Labs".into()));
+        let map = category_mapping(&sheet);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("血生化").map(String::as_str), Some("Labs"));
+    }
+}
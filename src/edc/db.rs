@@ -1,6 +1,9 @@
 use crate::ecrf::ECRF;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
 
 use super::{ecollect::db::EcollectDBStructReader, rave::db::RaveDBStructReader};
 
@@ -10,6 +13,21 @@ pub struct Form {
     pub name: String,
     pub page: usize,
     pub order: i32,
+    /// translated form name, such as english name for a chinese ecrf
+    #[serde(default)]
+    pub translation: Option<String>,
+    /// optional level between section and form in toc, such as `Labs`
+    #[serde(default)]
+    pub category: Option<String>,
+    /// identifier of form in edc, stable between versions of crf
+    #[serde(default)]
+    pub oid: Option<String>,
+    /// form excluded from toc and bookmarks by user
+    #[serde(default)]
+    pub hidden: bool,
+    /// values read from edc, none if form is added by user or saved before they were recorded
+    #[serde(default)]
+    pub imported: Option<Imported>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -17,22 +35,158 @@ pub struct Visit {
     pub(crate) id: usize,
     pub(crate) name: String,
     pub(crate) order: i32,
+    #[serde(default)]
+    pub(crate) translation: Option<String>,
+    #[serde(default)]
+    pub(crate) oid: Option<String>,
+    /// visit excluded from toc and bookmarks by user
+    #[serde(default)]
+    pub(crate) hidden: bool,
+    #[serde(default)]
+    pub(crate) imported: Option<Imported>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// values of form or visit as read from edc, values which differ from them are corrections of user
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Imported {
+    pub name: String,
+    #[serde(default)]
+    pub translation: Option<String>,
+    pub order: i32,
+    /// category of form, always none for visits
+    #[serde(default)]
+    pub category: Option<String>,
+    /// page of form in ecrf, always 0 for visits
+    #[serde(default)]
+    pub page: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FormVisitBinding {
     pub(crate) parent: usize,
     pub(crate) children: Vec<usize>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DBStruct {
     pub visit: Vec<Visit>,
     pub form: Vec<Form>,
     pub binding: Vec<FormVisitBinding>,
 }
 
-#[derive(Debug, Deserialize)]
+impl Visit {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn order(&self) -> i32 {
+        self.order
+    }
+
+    pub fn translation(&self) -> Option<&str> {
+        self.translation.as_deref()
+    }
+
+    /// identifier of visit in edc, such as folder oid of rave
+    pub fn oid(&self) -> Option<&str> {
+        self.oid.as_deref()
+    }
+
+    pub fn hidden(&self) -> bool {
+        self.hidden
+    }
+
+    /// values read from edc, none if visit is added by user or saved before they were recorded
+    pub fn imported(&self) -> Option<&Imported> {
+        self.imported.as_ref()
+    }
+}
+
+impl FormVisitBinding {
+    /// id of form
+    pub fn parent(&self) -> usize {
+        self.parent
+    }
+
+    /// ids of visits the form bound to
+    pub fn children(&self) -> &[usize] {
+        &self.children
+    }
+}
+
+impl DBStruct {
+    pub fn visit_by_id(&self, id: usize) -> Option<&Visit> {
+        self.visit.iter().find(|v| v.id.eq(&id))
+    }
+
+    pub fn form_by_id(&self, id: usize) -> Option<&Form> {
+        self.form.iter().find(|f| f.id.eq(&id))
+    }
+
+    /// ids of visits the form bound to
+    pub fn form_visits(&self, form: usize) -> Vec<usize> {
+        self.binding
+            .iter()
+            .filter(|b| b.parent.eq(&form))
+            .flat_map(|b| b.children.iter().cloned())
+            .collect()
+    }
+
+    /// ids of forms bound to the visit
+    pub fn visit_forms(&self, visit: usize) -> Vec<usize> {
+        self.binding
+            .iter()
+            .filter(|b| b.children.contains(&visit))
+            .map(|b| b.parent)
+            .collect()
+    }
+
+    /// record current values of forms and visits as the values read from edc
+    pub fn record_imported(&mut self) {
+        for visit in self.visit.iter_mut() {
+            visit.imported = Some(Imported {
+                name: visit.name.clone(),
+                translation: visit.translation.clone(),
+                order: visit.order,
+                category: None,
+                page: 0,
+            });
+        }
+        for form in self.form.iter_mut() {
+            form.imported = Some(Imported {
+                name: form.name.clone(),
+                translation: form.translation.clone(),
+                order: form.order,
+                category: form.category.clone(),
+                page: form.page,
+            });
+        }
+    }
+
+    /// set category of forms by form name, return names in mapping which match no form
+    pub fn apply_categories(&mut self, mapping: &HashMap<String, String>) -> Vec<String> {
+        let mut matched = HashSet::with_capacity(mapping.len());
+        for form in self.form.iter_mut() {
+            if let Some(category) = mapping.get(&form.name) {
+                form.category = Some(category.clone());
+                matched.insert(form.name.clone());
+            }
+        }
+        let mut unmatched = mapping
+            .keys()
+            .filter(|name| !matched.contains(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+        unmatched.sort();
+        unmatched
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DBKind {
     ECollect,
     Rave,
@@ -48,3 +202,54 @@ pub fn db_reader<P: AsRef<Path>>(kind: &DBKind) -> Box<dyn DBStructReader<P>> {
         DBKind::Rave => Box::new(RaveDBStructReader::new()),
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn form(id: usize, name: &str, page: usize) -> Form {
+        Form {
+            id,
+            name: name.into(),
+            page,
+            order: id as i32,
+            translation: None,
+            category: None,
+            oid: None,
+            hidden: false,
+            imported: None,
+        }
+    }
+
+    pub(crate) fn visit(id: usize, name: &str) -> Visit {
+        Visit {
+            id,
+            name: name.into(),
+            order: id as i32,
+            translation: None,
+            oid: None,
+            hidden: false,
+            imported: None,
+        }
+    }
+
+    pub(crate) fn bind(form: usize, visits: &[usize]) -> FormVisitBinding {
+        FormVisitBinding {
+            parent: form,
+            children: visits.to_vec(),
+        }
+    }
+
+    /// V1 and V2, Vital Signs on page 3 bound to both visits, Demographics on page 1 bound to V1
+    /// and ordered before Vital Signs
+    pub(crate) fn db() -> DBStruct {
+        let mut db = DBStruct {
+            visit: vec![visit(0, "V1"), visit(1, "V2")],
+            form: vec![form(0, "Vital Signs", 3), form(1, "Demographics", 1)],
+            binding: vec![bind(0, &[0, 1]), bind(1, &[0])],
+        };
+        db.form[0].order = 1;
+        db.form[1].order = 0;
+        db
+    }
+}
@@ -0,0 +1,460 @@
+use crate::edc::db::DBStruct;
+use rust_xlsxwriter::{Format, Workbook};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    path::Path,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Subject {
+    Form,
+    Visit,
+    Binding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Renamed,
+    PageChanged,
+    OrderChanged,
+}
+
+/// one change from old struct to new struct, `name` is the name in new struct if exists
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub subject: Subject,
+    pub kind: ChangeKind,
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Diff {
+    pub changes: Vec<Change>,
+}
+
+/// item to match between two structs
+pub(crate) struct Item<'a> {
+    pub(crate) id: usize,
+    pub(crate) oid: Option<&'a str>,
+    pub(crate) name: &'a str,
+}
+
+impl DBStruct {
+    /// changes from self to other, forms and visits are matched by oid if both have one, otherwise by name
+    pub fn diff(&self, other: &DBStruct) -> Diff {
+        let mut changes = vec![];
+
+        let old_forms = self
+            .form
+            .iter()
+            .map(|f| Item {
+                id: f.id,
+                oid: f.oid.as_deref(),
+                name: &f.name,
+            })
+            .collect::<Vec<_>>();
+        let new_forms = other
+            .form
+            .iter()
+            .map(|f| Item {
+                id: f.id,
+                oid: f.oid.as_deref(),
+                name: &f.name,
+            })
+            .collect::<Vec<_>>();
+        let form_pairs = match_items(&old_forms, &new_forms);
+        for (old, new) in form_pairs.iter() {
+            match (old, new) {
+                (Some(old), Some(new)) => {
+                    let (old, new) = (&self.form[*old], &other.form[*new]);
+                    if old.name.ne(&new.name) {
+                        changes.push(Change::new(
+                            Subject::Form,
+                            ChangeKind::Renamed,
+                            &new.name,
+                            Some(&old.name),
+                            Some(&new.name),
+                        ));
+                    }
+                    if old.page.ne(&new.page) {
+                        changes.push(Change::new(
+                            Subject::Form,
+                            ChangeKind::PageChanged,
+                            &new.name,
+                            Some(old.page),
+                            Some(new.page),
+                        ));
+                    }
+                    if old.order.ne(&new.order) {
+                        changes.push(Change::new(
+                            Subject::Form,
+                            ChangeKind::OrderChanged,
+                            &new.name,
+                            Some(old.order),
+                            Some(new.order),
+                        ));
+                    }
+                }
+                (Some(old), None) => changes.push(Change::new::<String>(
+                    Subject::Form,
+                    ChangeKind::Removed,
+                    &self.form[*old].name,
+                    None,
+                    None,
+                )),
+                (None, Some(new)) => changes.push(Change::new::<String>(
+                    Subject::Form,
+                    ChangeKind::Added,
+                    &other.form[*new].name,
+                    None,
+                    None,
+                )),
+                (None, None) => {}
+            }
+        }
+
+        let old_visits = self
+            .visit
+            .iter()
+            .map(|v| Item {
+                id: v.id,
+                oid: v.oid.as_deref(),
+                name: &v.name,
+            })
+            .collect::<Vec<_>>();
+        let new_visits = other
+            .visit
+            .iter()
+            .map(|v| Item {
+                id: v.id,
+                oid: v.oid.as_deref(),
+                name: &v.name,
+            })
+            .collect::<Vec<_>>();
+        let visit_pairs = match_items(&old_visits, &new_visits);
+        for (old, new) in visit_pairs.iter() {
+            match (old, new) {
+                (Some(old), Some(new)) => {
+                    let (old, new) = (&self.visit[*old], &other.visit[*new]);
+                    if old.name.ne(&new.name) {
+                        changes.push(Change::new(
+                            Subject::Visit,
+                            ChangeKind::Renamed,
+                            &new.name,
+                            Some(&old.name),
+                            Some(&new.name),
+                        ));
+                    }
+                    if old.order.ne(&new.order) {
+                        changes.push(Change::new(
+                            Subject::Visit,
+                            ChangeKind::OrderChanged,
+                            &new.name,
+                            Some(old.order),
+                            Some(new.order),
+                        ));
+                    }
+                }
+                (Some(old), None) => changes.push(Change::new::<String>(
+                    Subject::Visit,
+                    ChangeKind::Removed,
+                    &self.visit[*old].name,
+                    None,
+                    None,
+                )),
+                (None, Some(new)) => changes.push(Change::new::<String>(
+                    Subject::Visit,
+                    ChangeKind::Added,
+                    &other.visit[*new].name,
+                    None,
+                    None,
+                )),
+                (None, None) => {}
+            }
+        }
+
+        // compare bindings by the index of matched pairs, so that unstable ids do not matter
+        let old_form_key = pair_keys(&form_pairs, &old_forms, true);
+        let new_form_key = pair_keys(&form_pairs, &new_forms, false);
+        let old_visit_key = pair_keys(&visit_pairs, &old_visits, true);
+        let new_visit_key = pair_keys(&visit_pairs, &new_visits, false);
+        let old_binding = binding_keys(self, &old_form_key, &old_visit_key);
+        let new_binding = binding_keys(other, &new_form_key, &new_visit_key);
+        let pair_name = |pairs: &[(Option<usize>, Option<usize>)], key: usize, form: bool| {
+            let (old, new) = pairs[key];
+            match (form, new, old) {
+                (true, Some(new), _) => other.form[new].name.clone(),
+                (true, None, Some(old)) => self.form[old].name.clone(),
+                (false, Some(new), _) => other.visit[new].name.clone(),
+                (false, None, Some(old)) => self.visit[old].name.clone(),
+                _ => String::new(),
+            }
+        };
+        for (form, visit) in new_binding.difference(&old_binding) {
+            changes.push(Change::new::<String>(
+                Subject::Binding,
+                ChangeKind::Added,
+                &format!(
+                    "{} @ {}",
+                    pair_name(&form_pairs, *form, true),
+                    pair_name(&visit_pairs, *visit, false)
+                ),
+                None,
+                None,
+            ));
+        }
+        for (form, visit) in old_binding.difference(&new_binding) {
+            changes.push(Change::new::<String>(
+                Subject::Binding,
+                ChangeKind::Removed,
+                &format!(
+                    "{} @ {}",
+                    pair_name(&form_pairs, *form, true),
+                    pair_name(&visit_pairs, *visit, false)
+                ),
+                None,
+                None,
+            ));
+        }
+        Diff { changes }
+    }
+}
+
+impl Change {
+    fn new<T: Display>(
+        subject: Subject,
+        kind: ChangeKind,
+        name: &str,
+        before: Option<T>,
+        after: Option<T>,
+    ) -> Self {
+        Change {
+            subject,
+            kind,
+            name: name.into(),
+            before: before.map(|v| v.to_string()),
+            after: after.map(|v| v.to_string()),
+        }
+    }
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut lines = vec![
+            "| Subject | Change | Name | Before | After |".to_string(),
+            "| --- | --- | --- | --- | --- |".to_string(),
+        ];
+        for change in self.changes.iter() {
+            lines.push(format!(
+                "| {:?} | {:?} | {} | {} | {} |",
+                change.subject,
+                change.kind,
+                escape_markdown(&change.name),
+                escape_markdown(change.before.as_deref().unwrap_or_default()),
+                escape_markdown(change.after.as_deref().unwrap_or_default()),
+            ));
+        }
+        lines.join("\n")
+    }
+
+    pub fn write_xlsx<P: AsRef<Path>>(&self, p: P) -> anyhow::Result<()> {
+        let mut workbook = Workbook::new();
+        let header = Format::new().set_bold();
+        let sheet = workbook.add_worksheet();
+        sheet.set_name("Changes")?;
+        for (col, title) in ["Subject", "Change", "Name", "Before", "After"]
+            .iter()
+            .enumerate()
+        {
+            sheet.write_string_with_format(0, col as u16, *title, &header)?;
+        }
+        for (index, change) in self.changes.iter().enumerate() {
+            let row = index as u32 + 1;
+            sheet.write_string(row, 0, format!("{:?}", change.subject))?;
+            sheet.write_string(row, 1, format!("{:?}", change.kind))?;
+            sheet.write_string(row, 2, &change.name)?;
+            sheet.write_string(row, 3, change.before.as_deref().unwrap_or_default())?;
+            sheet.write_string(row, 4, change.after.as_deref().unwrap_or_default())?;
+        }
+        sheet.set_column_width(2, 40)?;
+        workbook.save(p)?;
+        Ok(())
+    }
+}
+
+/// pair items of old and new by oid first, then by name, return list of (old index, new index)
+pub(crate) fn match_items(old: &[Item], new: &[Item]) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut pairs = Vec::with_capacity(old.len().max(new.len()));
+    let mut new_matched = vec![false; new.len()];
+    let mut old_matched = vec![false; old.len()];
+    let new_oid = new
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| item.oid.map(|oid| (oid, i)))
+        .collect::<HashMap<_, _>>();
+    for (i, item) in old.iter().enumerate() {
+        if let Some(j) = item.oid.and_then(|oid| new_oid.get(oid)) {
+            if !new_matched[*j] {
+                pairs.push((Some(i), Some(*j)));
+                new_matched[*j] = true;
+                old_matched[i] = true;
+            }
+        }
+    }
+    for (i, item) in old.iter().enumerate() {
+        if old_matched[i] {
+            continue;
+        }
+        // items with oid on both sides only match by oid
+        let j = new.iter().enumerate().position(|(j, n)| {
+            !new_matched[j] && n.name.eq(item.name) && (n.oid.is_none() || item.oid.is_none())
+        });
+        match j {
+            Some(j) => {
+                pairs.push((Some(i), Some(j)));
+                new_matched[j] = true;
+            }
+            None => pairs.push((Some(i), None)),
+        }
+    }
+    for (j, matched) in new_matched.into_iter().enumerate() {
+        if !matched {
+            pairs.push((None, Some(j)));
+        }
+    }
+    pairs
+}
+
+/// map id of item to index of its pair
+fn pair_keys(
+    pairs: &[(Option<usize>, Option<usize>)],
+    items: &[Item],
+    old: bool,
+) -> HashMap<usize, usize> {
+    pairs
+        .iter()
+        .enumerate()
+        .filter_map(|(key, (o, n))| {
+            let index = if old { o } else { n };
+            index.map(|index| (items[index].id, key))
+        })
+        .collect()
+}
+
+fn binding_keys(
+    db: &DBStruct,
+    form_key: &HashMap<usize, usize>,
+    visit_key: &HashMap<usize, usize>,
+) -> BTreeSet<(usize, usize)> {
+    db.binding
+        .iter()
+        .filter_map(|b| form_key.get(&b.parent).map(|f| (f, &b.children)))
+        .flat_map(|(f, children)| {
+            children
+                .iter()
+                .filter_map(|v| visit_key.get(v).map(|v| (*f, *v)))
+        })
+        .collect()
+}
+
+fn escape_markdown(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edc::db::tests::{db, form};
+
+    fn has(diff: &Diff, subject: Subject, kind: ChangeKind, name: &str) -> bool {
+        diff.changes
+            .iter()
+            .any(|c| c.subject.eq(&subject) && c.kind.eq(&kind) && c.name.eq(name))
+    }
+
+    /// fixture with a new form inserted at the front, so ids of other forms shift by one
+    fn shifted() -> DBStruct {
+        let mut db = db();
+        for f in db.form.iter_mut() {
+            f.id += 1;
+        }
+        for b in db.binding.iter_mut() {
+            b.parent += 1;
+        }
+        db.form.insert(0, form(0, "Adverse Events", 2));
+        db
+    }
+
+    #[test]
+    fn diff_same_test() {
+        assert!(db().diff(&db()).is_empty());
+        // forms are matched by name when ids shift, only the inserted form is a change
+        assert_eq!(db().diff(&shifted()).changes.len(), 1);
+    }
+
+    #[test]
+    fn diff_oid_test() {
+        let mut old = db();
+        old.form[0].oid = Some("VS".into());
+        old.form[1].oid = Some("DM".into());
+        let mut new = old.clone();
+        new.form[0].page = 4;
+        new.form[1].name = "Demography".into();
+        let diff = old.diff(&new);
+        assert_eq!(diff.changes.len(), 2);
+        assert!(has(&diff, Subject::Form, ChangeKind::Renamed, "Demography"));
+        assert!(has(
+            &diff,
+            Subject::Form,
+            ChangeKind::PageChanged,
+            "Vital Signs"
+        ));
+    }
+
+    #[test]
+    fn diff_added_removed_test() {
+        let mut old = db();
+        old.form.push(form(2, "Death", 5));
+        let mut new = shifted();
+        // vital signs is no longer collected at V2
+        new.binding[0].children = vec![0];
+        let diff = old.diff(&new);
+        assert!(has(
+            &diff,
+            Subject::Form,
+            ChangeKind::Added,
+            "Adverse Events"
+        ));
+        assert!(has(&diff, Subject::Form, ChangeKind::Removed, "Death"));
+        assert!(has(
+            &diff,
+            Subject::Binding,
+            ChangeKind::Removed,
+            "Vital Signs @ V2"
+        ));
+        assert!(!has(
+            &diff,
+            Subject::Binding,
+            ChangeKind::Removed,
+            "Demographics @ V1"
+        ));
+    }
+
+    #[test]
+    fn to_markdown_test() {
+        let diff = db().diff(&shifted());
+        assert!(diff
+            .to_markdown()
+            .contains("| Form | Added | Adverse Events |  |  |"));
+    }
+}
@@ -1,11 +1,16 @@
 use crate::{
     ecrf::ECRF,
-    edc::db::{DBStruct, DBStructReader, Form, FormVisitBinding, Visit},
+    edc::{
+        category::category_mapping,
+        db::{DBStruct, DBStructReader, Form, FormVisitBinding, Visit},
+    },
 };
 use calamine::{open_workbook, DataType, Reader, Xlsx};
 use std::path::Path;
 
 const TARGET_SHEET: &str = "EventWorkflow";
+/// optional sheet with form name in column A and category in column B
+const CATEGORY_SHEET: &str = "FormCategory";
 
 pub struct EcollectDBStructReader;
 
@@ -36,6 +41,11 @@ impl<P: AsRef<Path>> DBStructReader<P> for EcollectDBStructReader {
                             id: col - 1,
                             name: v.into(),
                             order: col as i32 - 1,
+                            translation: None,
+                            // names are the only identifiers in building file
+                            oid: Some(v.into()),
+                            hidden: false,
+                            imported: None,
                         });
                     }
                 }
@@ -56,6 +66,11 @@ impl<P: AsRef<Path>> DBStructReader<P> for EcollectDBStructReader {
                         name: v.into(),
                         page: ecrf.form_page(v).unwrap_or_default(),
                         order: id as i32,
+                        translation: None,
+                        category: None,
+                        oid: Some(v.into()),
+                        hidden: false,
+                        imported: None,
                     });
                 }
                 for col in 1..column {
@@ -68,11 +83,16 @@ impl<P: AsRef<Path>> DBStructReader<P> for EcollectDBStructReader {
                 binding.push(b);
             }
         }
-        Ok(DBStruct {
+        let mut db = DBStruct {
             visit,
             form,
             binding,
-        })
+        };
+        if let Ok(sheet) = workbook.worksheet_range(CATEGORY_SHEET) {
+            db.apply_categories(&category_mapping(&sheet));
+        }
+        db.record_imported();
+        Ok(db)
     }
 }
 
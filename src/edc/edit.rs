@@ -0,0 +1,501 @@
+use crate::edc::db::{DBStruct, Form, FormVisitBinding, Visit};
+use anyhow::{anyhow, bail};
+use std::mem::replace;
+
+/// editing command on db struct, applying a command returns the command which reverts it
+#[derive(Debug, Clone)]
+pub enum Edit {
+    /// insert visit and bind it to forms, the visit id must not exist
+    InsertVisit {
+        visit: Visit,
+        forms: Vec<usize>,
+    },
+    /// remove visit and all its bindings
+    RemoveVisit(usize),
+    RenameVisit {
+        id: usize,
+        name: String,
+    },
+    /// move visit to position of visits sorted by order, orders of visits are renumbered from 0
+    MoveVisit {
+        id: usize,
+        position: usize,
+    },
+    /// set orders of visits, list of (visit id, order)
+    ReorderVisits(Vec<(usize, i32)>),
+    /// exclude visit from toc and bookmarks, or show it again
+    HideVisit {
+        id: usize,
+        hidden: bool,
+    },
+    /// insert form and bind it to visits, the form id must not exist
+    InsertForm {
+        form: Form,
+        visits: Vec<usize>,
+    },
+    /// remove form and all its bindings
+    RemoveForm(usize),
+    RenameForm {
+        id: usize,
+        name: String,
+    },
+    /// move form to position of forms sorted by order, orders of forms are renumbered from 0
+    MoveForm {
+        id: usize,
+        position: usize,
+    },
+    /// set orders of forms, list of (form id, order)
+    ReorderForms(Vec<(usize, i32)>),
+    /// exclude form from toc and bookmarks, or show it again
+    HideForm {
+        id: usize,
+        hidden: bool,
+    },
+    Bind {
+        form: usize,
+        visit: usize,
+    },
+    Unbind {
+        form: usize,
+        visit: usize,
+    },
+    /// bind forms of visit `from` to visit `into`, then remove visit `from`
+    MergeVisits {
+        from: usize,
+        into: usize,
+    },
+    /// apply edits in order, all edits are reverted if any of them fails
+    Batch(Vec<Edit>),
+}
+
+impl Edit {
+    /// insert a new visit after all visits, with an unused id
+    pub fn add_visit(db: &DBStruct, name: &str) -> Edit {
+        Edit::InsertVisit {
+            visit: Visit {
+                id: db.visit.iter().map(|v| v.id + 1).max().unwrap_or_default(),
+                name: name.into(),
+                order: db
+                    .visit
+                    .iter()
+                    .map(|v| v.order + 1)
+                    .max()
+                    .unwrap_or_default(),
+                translation: None,
+                oid: None,
+                hidden: false,
+                imported: None,
+            },
+            forms: vec![],
+        }
+    }
+
+    /// insert a new form after all forms, with an unused id
+    pub fn add_form(db: &DBStruct, name: &str, page: usize) -> Edit {
+        Edit::InsertForm {
+            form: Form {
+                id: db.form.iter().map(|f| f.id + 1).max().unwrap_or_default(),
+                name: name.into(),
+                page,
+                order: db
+                    .form
+                    .iter()
+                    .map(|f| f.order + 1)
+                    .max()
+                    .unwrap_or_default(),
+                translation: None,
+                category: None,
+                oid: None,
+                hidden: false,
+                imported: None,
+            },
+            visits: vec![],
+        }
+    }
+
+    /// apply edit on db struct, return the edit which reverts it
+    pub fn apply(self, db: &mut DBStruct) -> anyhow::Result<Edit> {
+        Ok(match self {
+            Edit::InsertVisit { visit, forms } => {
+                if db.visit_by_id(visit.id).is_some() {
+                    bail!("visit id {} already exists", visit.id);
+                }
+                for form in forms.iter() {
+                    form_index(db, *form)?;
+                }
+                let id = visit.id;
+                db.visit.push(visit);
+                for form in forms {
+                    bind(db, form, id);
+                }
+                Edit::RemoveVisit(id)
+            }
+            Edit::RemoveVisit(id) => {
+                let index = visit_index(db, id)?;
+                let forms = db.visit_forms(id);
+                let visit = db.visit.remove(index);
+                for binding in db.binding.iter_mut() {
+                    binding.children.retain(|v| !v.eq(&id));
+                }
+                Edit::InsertVisit { visit, forms }
+            }
+            Edit::RenameVisit { id, name } => {
+                let index = visit_index(db, id)?;
+                let name = replace(&mut db.visit[index].name, name);
+                Edit::RenameVisit { id, name }
+            }
+            Edit::MoveVisit { id, position } => {
+                visit_index(db, id)?;
+                let orders = db.visit.iter().map(|v| (v.id, v.order)).collect::<Vec<_>>();
+                let ids = move_to(&orders, id, position);
+                Edit::ReorderVisits(renumber(&ids)).apply(db)?;
+                Edit::ReorderVisits(orders)
+            }
+            Edit::ReorderVisits(orders) => {
+                let mut revert = Vec::with_capacity(orders.len());
+                for (id, _) in orders.iter() {
+                    let index = visit_index(db, *id)?;
+                    revert.push((*id, db.visit[index].order));
+                }
+                for (id, order) in orders {
+                    let index = visit_index(db, id)?;
+                    db.visit[index].order = order;
+                }
+                Edit::ReorderVisits(revert)
+            }
+            Edit::HideVisit { id, hidden } => {
+                let index = visit_index(db, id)?;
+                let hidden = replace(&mut db.visit[index].hidden, hidden);
+                Edit::HideVisit { id, hidden }
+            }
+            Edit::InsertForm { form, visits } => {
+                if db.form_by_id(form.id).is_some() {
+                    bail!("form id {} already exists", form.id);
+                }
+                for visit in visits.iter() {
+                    visit_index(db, *visit)?;
+                }
+                let id = form.id;
+                db.form.push(form);
+                for visit in visits {
+                    bind(db, id, visit);
+                }
+                Edit::RemoveForm(id)
+            }
+            Edit::RemoveForm(id) => {
+                let index = form_index(db, id)?;
+                let visits = db.form_visits(id);
+                let form = db.form.remove(index);
+                db.binding.retain(|b| !b.parent.eq(&id));
+                Edit::InsertForm { form, visits }
+            }
+            Edit::RenameForm { id, name } => {
+                let index = form_index(db, id)?;
+                let name = replace(&mut db.form[index].name, name);
+                Edit::RenameForm { id, name }
+            }
+            Edit::MoveForm { id, position } => {
+                form_index(db, id)?;
+                let orders = db.form.iter().map(|f| (f.id, f.order)).collect::<Vec<_>>();
+                let ids = move_to(&orders, id, position);
+                Edit::ReorderForms(renumber(&ids)).apply(db)?;
+                Edit::ReorderForms(orders)
+            }
+            Edit::ReorderForms(orders) => {
+                let mut revert = Vec::with_capacity(orders.len());
+                for (id, _) in orders.iter() {
+                    let index = form_index(db, *id)?;
+                    revert.push((*id, db.form[index].order));
+                }
+                for (id, order) in orders {
+                    let index = form_index(db, id)?;
+                    db.form[index].order = order;
+                }
+                Edit::ReorderForms(revert)
+            }
+            Edit::HideForm { id, hidden } => {
+                let index = form_index(db, id)?;
+                let hidden = replace(&mut db.form[index].hidden, hidden);
+                Edit::HideForm { id, hidden }
+            }
+            Edit::Bind { form, visit } => {
+                form_index(db, form)?;
+                visit_index(db, visit)?;
+                if bind(db, form, visit) {
+                    Edit::Unbind { form, visit }
+                } else {
+                    Edit::Batch(vec![])
+                }
+            }
+            Edit::Unbind { form, visit } => {
+                form_index(db, form)?;
+                visit_index(db, visit)?;
+                if unbind(db, form, visit) {
+                    Edit::Bind { form, visit }
+                } else {
+                    Edit::Batch(vec![])
+                }
+            }
+            Edit::MergeVisits { from, into } => {
+                if from.eq(&into) {
+                    bail!("can not merge visit {} into itself", from);
+                }
+                visit_index(db, from)?;
+                visit_index(db, into)?;
+                let mut edits = db
+                    .visit_forms(from)
+                    .into_iter()
+                    .map(|form| Edit::Bind { form, visit: into })
+                    .collect::<Vec<_>>();
+                edits.push(Edit::RemoveVisit(from));
+                Edit::Batch(edits).apply(db)?
+            }
+            Edit::Batch(edits) => {
+                let mut revert = Vec::with_capacity(edits.len());
+                for edit in edits {
+                    match edit.apply(db) {
+                        Ok(edit) => revert.push(edit),
+                        Err(e) => {
+                            for edit in revert.into_iter().rev() {
+                                edit.apply(db)?;
+                            }
+                            return Err(e);
+                        }
+                    }
+                }
+                revert.reverse();
+                Edit::Batch(revert)
+            }
+        })
+    }
+}
+
+/// undo and redo stacks of edits applied on a db struct
+#[derive(Debug, Default)]
+pub struct EditHistory {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl EditHistory {
+    pub fn apply(&mut self, db: &mut DBStruct, edit: Edit) -> anyhow::Result<()> {
+        let revert = edit.apply(db)?;
+        self.undo.push(revert);
+        self.redo.clear();
+        Ok(())
+    }
+
+    /// revert last edit, return false if there is nothing to undo
+    pub fn undo(&mut self, db: &mut DBStruct) -> anyhow::Result<bool> {
+        match self.undo.pop() {
+            Some(edit) => match edit.clone().apply(db) {
+                Ok(redo) => {
+                    self.redo.push(redo);
+                    Ok(true)
+                }
+                Err(e) => {
+                    self.undo.push(edit);
+                    Err(e)
+                }
+            },
+            None => Ok(false),
+        }
+    }
+
+    /// apply last undone edit again, return false if there is nothing to redo
+    pub fn redo(&mut self, db: &mut DBStruct) -> anyhow::Result<bool> {
+        match self.redo.pop() {
+            Some(edit) => match edit.clone().apply(db) {
+                Ok(undo) => {
+                    self.undo.push(undo);
+                    Ok(true)
+                }
+                Err(e) => {
+                    self.redo.push(edit);
+                    Err(e)
+                }
+            },
+            None => Ok(false),
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+fn visit_index(db: &DBStruct, id: usize) -> anyhow::Result<usize> {
+    db.visit
+        .iter()
+        .position(|v| v.id.eq(&id))
+        .ok_or(anyhow!("visit id {} does not exist", id))
+}
+
+fn form_index(db: &DBStruct, id: usize) -> anyhow::Result<usize> {
+    db.form
+        .iter()
+        .position(|f| f.id.eq(&id))
+        .ok_or(anyhow!("form id {} does not exist", id))
+}
+
+/// add visit into binding of form, return false if they are bound already
+fn bind(db: &mut DBStruct, form: usize, visit: usize) -> bool {
+    match db.binding.iter_mut().find(|b| b.parent.eq(&form)) {
+        Some(binding) => {
+            if binding.children.contains(&visit) {
+                return false;
+            }
+            binding.children.push(visit);
+        }
+        None => db.binding.push(FormVisitBinding {
+            parent: form,
+            children: vec![visit],
+        }),
+    }
+    true
+}
+
+/// remove visit from binding of form, return false if they are not bound
+fn unbind(db: &mut DBStruct, form: usize, visit: usize) -> bool {
+    let mut removed = false;
+    for binding in db.binding.iter_mut().filter(|b| b.parent.eq(&form)) {
+        let len = binding.children.len();
+        binding.children.retain(|v| !v.eq(&visit));
+        removed = removed || len.ne(&binding.children.len());
+    }
+    removed
+}
+
+/// ids sorted by order, with `id` moved to `position`
+fn move_to(orders: &[(usize, i32)], id: usize, position: usize) -> Vec<usize> {
+    let mut orders = orders.to_vec();
+    orders.sort_by_key(|(_, order)| *order);
+    let mut ids = orders
+        .into_iter()
+        .map(|(i, _)| i)
+        .filter(|i| !i.eq(&id))
+        .collect::<Vec<_>>();
+    ids.insert(position.min(ids.len()), id);
+    ids
+}
+
+fn renumber(ids: &[usize]) -> Vec<(usize, i32)> {
+    ids.iter()
+        .enumerate()
+        .map(|(order, id)| (*id, order as i32))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edc::db::tests::db;
+    use std::collections::BTreeSet;
+
+    type Snapshot = (
+        BTreeSet<(usize, String, i32)>,
+        BTreeSet<(usize, String, i32)>,
+        BTreeSet<(usize, usize)>,
+    );
+
+    fn snapshot(db: &DBStruct) -> Snapshot {
+        (
+            db.visit
+                .iter()
+                .map(|v| (v.id, v.name.clone(), v.order))
+                .collect(),
+            db.form
+                .iter()
+                .map(|f| (f.id, f.name.clone(), f.order))
+                .collect(),
+            db.binding
+                .iter()
+                .flat_map(|b| b.children.iter().map(|v| (b.parent, *v)))
+                .collect(),
+        )
+    }
+
+    /// apply edits, then check that undoing all of them restores the origin struct
+    fn apply_and_undo(db: &mut DBStruct, edits: Vec<Edit>) -> anyhow::Result<Snapshot> {
+        let origin = snapshot(db);
+        let mut history = EditHistory::default();
+        for edit in edits {
+            history.apply(db, edit)?;
+        }
+        let edited = snapshot(db);
+        while history.undo(db)? {}
+        assert_eq!(snapshot(db), origin);
+        while history.redo(db)? {}
+        assert_eq!(snapshot(db), edited);
+        Ok(edited)
+    }
+
+    #[test]
+    fn undo_redo_test() -> anyhow::Result<()> {
+        let mut db = db();
+        let mut history = EditHistory::default();
+        let rename = Edit::RenameVisit {
+            id: 0,
+            name: "Screening".into(),
+        };
+        history.apply(&mut db, rename)?;
+        assert_eq!(db.visit_by_id(0).unwrap().name(), "Screening");
+        assert!(history.undo(&mut db)?);
+        assert_eq!(db.visit_by_id(0).unwrap().name(), "V1");
+        assert!(!history.can_undo());
+        assert!(history.redo(&mut db)?);
+        assert_eq!(db.visit_by_id(0).unwrap().name(), "Screening");
+        assert!(!history.can_redo());
+        Ok(())
+    }
+
+    #[test]
+    fn move_form_test() -> anyhow::Result<()> {
+        let mut db = db();
+        apply_and_undo(&mut db, vec![Edit::MoveForm { id: 0, position: 0 }])?;
+        assert_eq!(db.form_by_id(0).unwrap().order, 0);
+        assert_eq!(db.form_by_id(1).unwrap().order, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn merge_visits_test() -> anyhow::Result<()> {
+        let mut db = db();
+        apply_and_undo(&mut db, vec![Edit::MergeVisits { from: 1, into: 0 }])?;
+        assert!(db.visit_by_id(1).is_none());
+        assert_eq!(db.form_visits(0), vec![0]);
+        Ok(())
+    }
+
+    #[test]
+    fn add_visit_test() -> anyhow::Result<()> {
+        let mut db = db();
+        let add = Edit::add_visit(&db, "EOT");
+        apply_and_undo(&mut db, vec![add, Edit::Bind { form: 1, visit: 2 }])?;
+        assert_eq!(db.visit_by_id(2).unwrap().order(), 2);
+        assert_eq!(db.form_visits(1), vec![0, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn remove_form_test() -> anyhow::Result<()> {
+        let mut db = db();
+        apply_and_undo(&mut db, vec![Edit::RemoveForm(0)])?;
+        assert!(db.form_by_id(0).is_none());
+        assert!(db.visit_forms(1).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn failed_batch_test() {
+        let mut db = db();
+        let origin = snapshot(&db);
+        let result = Edit::Batch(vec![Edit::RemoveVisit(0), Edit::RemoveVisit(9)]).apply(&mut db);
+        assert!(result.is_err());
+        assert_eq!(snapshot(&db), origin);
+    }
+}
@@ -0,0 +1,479 @@
+use crate::edc::{
+    db::{DBStruct, Form, Imported, Visit},
+    diff::{match_items, Item, Subject},
+};
+use serde::Serialize;
+use std::fmt::Display;
+
+/// which manual corrections of saved struct are carried over to the re-imported one, a correction
+/// is a value which differs from the value read from edc when the saved struct was imported
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    /// names and translations of forms and visits
+    pub names: bool,
+    /// order of forms and visits
+    pub orders: bool,
+    /// hidden flags of forms and visits
+    pub hidden: bool,
+    /// category of forms
+    pub categories: bool,
+    pub pages: PageMerge,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PageMerge {
+    /// always use pages read from ecrf
+    Never,
+    /// keep saved page if form is not found in ecrf, or if page was corrected by user and
+    /// ecrf still has the page read at last import
+    #[default]
+    Missing,
+    /// always keep saved page
+    Always,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            names: true,
+            orders: true,
+            hidden: true,
+            categories: true,
+            pages: PageMerge::default(),
+        }
+    }
+}
+
+/// correction which could not be carried over
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub subject: Subject,
+    /// name in saved struct
+    pub name: String,
+    pub message: String,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} {}: {}", self.subject, self.name, self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Merge {
+    pub db: DBStruct,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl DBStruct {
+    /// apply corrections of self (saved struct) to struct freshly read from edc, other values follow
+    /// the fresh struct, so changes in edc are not reverted by stale saved values,
+    /// forms and visits are matched like `diff`, ids and bindings follow the fresh struct
+    pub fn merge(&self, fresh: DBStruct, options: &MergeOptions) -> Merge {
+        let mut db = fresh;
+        // values of fresh struct are the base of corrections made after this merge
+        db.record_imported();
+        let mut conflicts = vec![];
+
+        let saved_visits = self
+            .visit
+            .iter()
+            .map(|v| Item {
+                id: v.id,
+                oid: v.oid.as_deref(),
+                name: &v.name,
+            })
+            .collect::<Vec<_>>();
+        let fresh_visits = db
+            .visit
+            .iter()
+            .map(|v| Item {
+                id: v.id,
+                oid: v.oid.as_deref(),
+                name: &v.name,
+            })
+            .collect::<Vec<_>>();
+        let visit_pairs = match_items(&saved_visits, &fresh_visits);
+
+        let saved_forms = self
+            .form
+            .iter()
+            .map(|f| Item {
+                id: f.id,
+                oid: f.oid.as_deref(),
+                name: &f.name,
+            })
+            .collect::<Vec<_>>();
+        let fresh_forms = db
+            .form
+            .iter()
+            .map(|f| Item {
+                id: f.id,
+                oid: f.oid.as_deref(),
+                name: &f.name,
+            })
+            .collect::<Vec<_>>();
+        let form_pairs = match_items(&saved_forms, &fresh_forms);
+
+        let mut reordered = false;
+        let mut visit_added = vec![];
+        for pair in visit_pairs {
+            match pair {
+                (Some(old), Some(new)) => {
+                    let (old, new) = (&self.visit[old], &mut db.visit[new]);
+                    let corrections = Corrections::visit(old);
+                    if options.names && corrections.name {
+                        new.name = old.name.clone();
+                        new.translation = old.translation.clone();
+                    }
+                    if options.orders && corrections.order {
+                        new.order = old.order;
+                        reordered = true;
+                    }
+                    if options.hidden && corrections.hidden {
+                        new.hidden = old.hidden;
+                    }
+                }
+                (Some(old), None) => {
+                    if Corrections::visit(&self.visit[old]).any() {
+                        conflicts.push(Conflict {
+                            subject: Subject::Visit,
+                            name: self.visit[old].name.clone(),
+                            message: "visit no longer exists in edc, corrections are dropped"
+                                .into(),
+                        });
+                    }
+                }
+                (None, Some(new)) => visit_added.push(new),
+                (None, None) => {}
+            }
+        }
+
+        let mut form_added = vec![];
+        for pair in form_pairs {
+            match pair {
+                (Some(old), Some(new)) => {
+                    let (old, new) = (&self.form[old], &mut db.form[new]);
+                    let corrections = Corrections::form(old);
+                    if options.names && corrections.name {
+                        new.name = old.name.clone();
+                        new.translation = old.translation.clone();
+                    }
+                    if options.orders && corrections.order {
+                        new.order = old.order;
+                        reordered = true;
+                    }
+                    if options.hidden && corrections.hidden {
+                        new.hidden = old.hidden;
+                    }
+                    if options.categories && corrections.category {
+                        new.category = old.category.clone();
+                    }
+                    match options.pages {
+                        PageMerge::Always => {
+                            if new.page > 0 && new.page != old.page {
+                                conflicts.push(Conflict {
+                                    subject: Subject::Form,
+                                    name: old.name.clone(),
+                                    message: format!(
+                                        "page {} in ecrf is replaced by saved page {}",
+                                        new.page, old.page
+                                    ),
+                                });
+                            }
+                            new.page = old.page;
+                        }
+                        PageMerge::Missing if new.page == 0 => new.page = old.page,
+                        _ if !corrections.page || new.page == old.page => {}
+                        // ecrf is unchanged since last import, so correction still applies
+                        PageMerge::Missing
+                            if old.imported.as_ref().is_some_and(|i| i.page == new.page) =>
+                        {
+                            new.page = old.page
+                        }
+                        _ => conflicts.push(Conflict {
+                            subject: Subject::Form,
+                            name: old.name.clone(),
+                            message: format!(
+                                "corrected page {} is replaced by page {} in ecrf",
+                                old.page, new.page
+                            ),
+                        }),
+                    }
+                }
+                (Some(old), None) => {
+                    if Corrections::form(&self.form[old]).any() {
+                        conflicts.push(Conflict {
+                            subject: Subject::Form,
+                            name: self.form[old].name.clone(),
+                            message: "form no longer exists in edc, corrections are dropped".into(),
+                        });
+                    }
+                }
+                (None, Some(new)) => form_added.push(new),
+                (None, None) => {}
+            }
+        }
+
+        // saved orders do not fit orders of items new in edc, so new items are placed after
+        // saved ones, keeping their relative order
+        if reordered {
+            let last = db
+                .visit
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !visit_added.contains(i))
+                .map(|(_, v)| v.order)
+                .max()
+                .unwrap_or(-1);
+            visit_added.sort_by_key(|i| db.visit[*i].order);
+            for (n, i) in visit_added.into_iter().enumerate() {
+                db.visit[i].order = last + n as i32 + 1;
+            }
+            let last = db
+                .form
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !form_added.contains(i))
+                .map(|(_, f)| f.order)
+                .max()
+                .unwrap_or(-1);
+            form_added.sort_by_key(|i| db.form[*i].order);
+            for (n, i) in form_added.into_iter().enumerate() {
+                db.form[i].order = last + n as i32 + 1;
+            }
+        }
+
+        Merge { db, conflicts }
+    }
+}
+
+/// values of saved form or visit changed by user, all values are taken as changed if the values
+/// read from edc were not recorded
+struct Corrections {
+    name: bool,
+    order: bool,
+    category: bool,
+    hidden: bool,
+    page: bool,
+}
+
+impl Corrections {
+    fn new(
+        name: &str,
+        translation: Option<&str>,
+        order: i32,
+        category: Option<&str>,
+        page: usize,
+        hidden: bool,
+        imported: Option<&Imported>,
+    ) -> Self {
+        match imported {
+            Some(imported) => Corrections {
+                name: imported.name.ne(name) || imported.translation.as_deref().ne(&translation),
+                order: imported.order.ne(&order),
+                category: imported.category.as_deref().ne(&category),
+                hidden,
+                page: imported.page.ne(&page),
+            },
+            None => Corrections {
+                name: true,
+                order: true,
+                category: category.is_some(),
+                hidden,
+                page: page > 0,
+            },
+        }
+    }
+
+    fn visit(visit: &Visit) -> Self {
+        Corrections::new(
+            &visit.name,
+            visit.translation.as_deref(),
+            visit.order,
+            None,
+            0,
+            visit.hidden,
+            visit.imported.as_ref(),
+        )
+    }
+
+    fn form(form: &Form) -> Self {
+        Corrections::new(
+            &form.name,
+            form.translation.as_deref(),
+            form.order,
+            form.category.as_deref(),
+            form.page,
+            form.hidden,
+            form.imported.as_ref(),
+        )
+    }
+
+    fn any(&self) -> bool {
+        self.name || self.order || self.category || self.hidden || self.page
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edc::db::tests::{db, form, visit};
+
+    /// fixture as read from edc, with oids of forms
+    fn imported() -> DBStruct {
+        let mut db = db();
+        db.form[0].oid = Some("VS".into());
+        db.form[1].oid = Some("DM".into());
+        db.record_imported();
+        db
+    }
+
+    #[test]
+    fn merge_names_test() {
+        let mut saved = imported();
+        saved.form[1].name = "Demography".into();
+        let mut fresh = imported();
+        fresh.form[0].name = "Vital Sign Measurements".into();
+        let merge = saved.merge(fresh, &MergeOptions::default());
+        assert_eq!(merge.db.form[0].name, "Vital Sign Measurements");
+        assert_eq!(merge.db.form[1].name, "Demography");
+        assert!(merge.conflicts.is_empty());
+
+        // correction is compared with the newly imported name afterwards
+        let imported = merge.db.form[1].imported.as_ref().unwrap();
+        assert_eq!(imported.name, "Demographics");
+    }
+
+    #[test]
+    fn merge_orders_test() {
+        // orders changed in edc are kept if user did not change them
+        let mut fresh = imported();
+        fresh.form[0].order = 0;
+        fresh.form[1].order = 1;
+        let merge = imported().merge(fresh.clone(), &MergeOptions::default());
+        assert_eq!(merge.db.form[0].order, 0);
+        assert_eq!(merge.db.form[1].order, 1);
+
+        // new forms are placed after forms ordered by user
+        let mut saved = imported();
+        saved.visit[0].order = 1;
+        saved.visit[1].order = 0;
+        fresh.form.push(form(2, "Adverse Events", 4));
+        fresh.form[2].order = -1;
+        fresh.visit.push(visit(2, "V3"));
+        let merge = saved.merge(fresh, &MergeOptions::default());
+        assert_eq!(merge.db.visit[0].order, 1);
+        assert_eq!(merge.db.visit[1].order, 0);
+        assert_eq!(merge.db.visit[2].order, 2);
+        assert_eq!(merge.db.form[2].order, 2);
+    }
+
+    #[test]
+    fn merge_hidden_test() {
+        let mut saved = imported();
+        saved.form[1].hidden = true;
+        saved.visit[1].hidden = true;
+        saved.form[0].category = Some("Vitals".into());
+        let merge = saved.merge(imported(), &MergeOptions::default());
+        assert!(merge.db.form[1].hidden);
+        assert!(merge.db.visit[1].hidden);
+        assert_eq!(merge.db.form[0].category.as_deref(), Some("Vitals"));
+
+        let options = MergeOptions {
+            hidden: false,
+            ..Default::default()
+        };
+        let merge = saved.merge(imported(), &options);
+        assert!(!merge.db.form[1].hidden);
+        assert_eq!(merge.db.form[0].category.as_deref(), Some("Vitals"));
+
+        let options = MergeOptions {
+            categories: false,
+            ..Default::default()
+        };
+        let merge = saved.merge(imported(), &options);
+        assert!(merge.db.form[1].hidden);
+        assert!(merge.db.form[0].category.is_none());
+    }
+
+    #[test]
+    fn merge_pages_test() {
+        let mut fresh = imported();
+        fresh.form[0].page = 0;
+        fresh.form[1].page = 2;
+        let merge = imported().merge(fresh.clone(), &MergeOptions::default());
+        assert_eq!(merge.db.form[0].page, 3);
+        assert_eq!(merge.db.form[1].page, 2);
+        assert!(merge.conflicts.is_empty());
+
+        let options = MergeOptions {
+            pages: PageMerge::Always,
+            ..Default::default()
+        };
+        let merge = imported().merge(fresh, &options);
+        assert_eq!(merge.db.form[1].page, 1);
+        assert_eq!(merge.conflicts.len(), 1);
+    }
+
+    #[test]
+    fn merge_corrected_pages_test() {
+        let mut saved = imported();
+        saved.form[0].page = 4;
+        // ecrf still has the imported page, so the correction is kept
+        let merge = saved.merge(imported(), &MergeOptions::default());
+        assert_eq!(merge.db.form[0].page, 4);
+        assert!(merge.conflicts.is_empty());
+
+        let mut fresh = imported();
+        fresh.form[0].page = 5;
+        let merge = saved.merge(fresh, &MergeOptions::default());
+        assert_eq!(merge.db.form[0].page, 5);
+        assert_eq!(merge.conflicts.len(), 1);
+        assert_eq!(
+            merge.conflicts[0].message,
+            "corrected page 4 is replaced by page 5 in ecrf"
+        );
+
+        let options = MergeOptions {
+            pages: PageMerge::Never,
+            ..Default::default()
+        };
+        let merge = saved.merge(imported(), &options);
+        assert_eq!(merge.db.form[0].page, 3);
+        assert_eq!(merge.conflicts.len(), 1);
+    }
+
+    #[test]
+    fn merge_removed_test() {
+        let mut saved = imported();
+        saved.form.push(form(2, "Death", 5));
+        saved.visit.push(visit(2, "V3"));
+        saved.record_imported();
+        // items without corrections are dropped quietly
+        let merge = saved.merge(imported(), &MergeOptions::default());
+        assert!(merge.conflicts.is_empty());
+
+        saved.form[2].name = "Death Report".into();
+        saved.visit[2].hidden = true;
+        let merge = saved.merge(imported(), &MergeOptions::default());
+        assert_eq!(merge.conflicts.len(), 2);
+        assert_eq!(merge.conflicts[0].name, "V3");
+        assert_eq!(merge.conflicts[1].name, "Death Report");
+    }
+
+    #[test]
+    fn merge_unrecorded_test() {
+        // values read from edc are unknown, so every saved value is kept
+        let mut saved = imported();
+        for f in saved.form.iter_mut() {
+            f.imported = None;
+        }
+        saved.form[0].name = "Vitals".into();
+        let mut fresh = imported();
+        fresh.form[1].order = 5;
+        let merge = saved.merge(fresh, &MergeOptions::default());
+        assert_eq!(merge.db.form[0].name, "Vitals");
+        assert_eq!(merge.db.form[1].order, 0);
+    }
+}
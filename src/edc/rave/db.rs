@@ -5,6 +5,13 @@ use crate::{
 };
 use std::{collections::HashMap, path::Path};
 
+/// header of column in sheet Forms which stores the draft translation of form name
+const FORM_TRANSLATION_HEADER: &str = "DraftFormNameTranslation";
+/// header of column in sheet Folders which stores the draft translation of folder name
+const FOLDER_TRANSLATION_HEADER: &str = "FolderNameTranslation";
+/// header of column in sheet Forms which stores the category (form folder) of form
+const FORM_CATEGORY_HEADER: &str = "FormFolder";
+
 pub struct RaveDBStructReader;
 
 impl RaveDBStructReader {
@@ -25,6 +32,9 @@ impl<P: AsRef<Path>> DBStructReader<P> for RaveDBStructReader {
         let mut binding = Vec::with_capacity(forms.len());
         let form_map = build_form_map(&forms);
         let folder_map = build_folders_map(&folders);
+        let form_translation = build_column_map(&forms, FORM_TRANSLATION_HEADER);
+        let folder_translation = build_column_map(&folders, FOLDER_TRANSLATION_HEADER);
+        let form_category = build_column_map(&forms, FORM_CATEGORY_HEADER);
         // handle matrix sheet
         let visits = matrixs.first().cloned().unwrap_or_default();
         let visits = visits.get(1..).unwrap_or_default().to_vec();
@@ -35,12 +45,20 @@ impl<P: AsRef<Path>> DBStructReader<P> for RaveDBStructReader {
                         id: index,
                         name: name.to_owned(),
                         order: index as i32,
+                        translation: folder_translation.get(visit_oid).cloned(),
+                        oid: Some(visit_oid.clone()),
+                        hidden: false,
+                        imported: None,
                     });
                 } else if visit_oid.eq("Subject") {
                     visit.push(Visit {
                         id: index,
                         name: visit_oid.to_string(),
                         order: index as i32,
+                        translation: None,
+                        oid: Some(visit_oid.clone()),
+                        hidden: false,
+                        imported: None,
                     });
                 }
             }
@@ -58,6 +76,11 @@ impl<P: AsRef<Path>> DBStructReader<P> for RaveDBStructReader {
                             name: form_name.clone(),
                             page: form_page,
                             order: form_id as i32,
+                            translation: form_translation.get(form_oid).cloned(),
+                            category: form_category.get(form_oid).cloned(),
+                            oid: Some(form_oid.clone()),
+                            hidden: false,
+                            imported: None,
                         });
                     }
                 }
@@ -75,11 +98,13 @@ impl<P: AsRef<Path>> DBStructReader<P> for RaveDBStructReader {
                 }
             }
         }
-        Ok(DBStruct {
+        let mut db = DBStruct {
             visit,
             form,
             binding,
-        })
+        };
+        db.record_imported();
+        Ok(db)
     }
 }
 
@@ -106,6 +131,25 @@ fn build_folders_map(sheet: &[Vec<Option<String>>]) -> HashMap<String, String> {
     build_form_map(sheet)
 }
 
+/// build hash map for an optional column located by header, return HashMap<oid, value>, empty if the sheet has no such column
+fn build_column_map(sheet: &[Vec<Option<String>>], header: &str) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    let column = sheet.first().and_then(|row| {
+        row.iter()
+            .position(|cell| cell.as_deref().map(str::trim).eq(&Some(header)))
+    });
+    if let Some(column) = column {
+        for row in sheet.iter().skip(1) {
+            if let (Some(Some(oid)), Some(Some(value))) = (row.first(), row.get(column)) {
+                if !value.trim().is_empty() {
+                    map.insert(oid.clone(), value.trim().to_string());
+                }
+            }
+        }
+    }
+    map
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -125,4 +169,23 @@ mod tests {
         assert_eq!(result.visit.len(), 57);
         Ok(())
     }
+
+    #[test]
+    fn build_column_map_test() {
+        let cell = |s: &str| Some(s.to_string());
+        let sheet = vec![
+            vec![
+                cell("OID"),
+                cell("Ordinal"),
+                cell("DraftFormName"),
+                cell(FORM_TRANSLATION_HEADER),
+            ],
+            vec![cell("VS"), cell("1"), cell("生命体征"), cell("Vital Signs")],
+            vec![cell("DM"), cell("2"), cell("人口统计学资料"), None],
+        ];
+        let map = build_column_map(&sheet, FORM_TRANSLATION_HEADER);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get("VS").map(String::as_str), Some("Vital Signs"));
+        assert!(build_column_map(&sheet, FOLDER_TRANSLATION_HEADER).is_empty());
+    }
 }
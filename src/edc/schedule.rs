@@ -0,0 +1,198 @@
+use crate::edc::db::DBStruct;
+use rust_xlsxwriter::{Format, FormatAlign, Workbook};
+use std::{collections::HashSet, fs, path::Path};
+use tera::escape_html;
+
+const MARK: &str = "X";
+
+/// schedule of assessments, forms as rows and visits as columns, both sorted by order,
+/// hidden forms and visits are left out like in toc
+#[derive(Debug, Clone, Default)]
+pub struct Schedule {
+    pub visits: Vec<String>,
+    pub rows: Vec<ScheduleRow>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleRow {
+    pub form: String,
+    /// one mark for each visit, true if form is bound to the visit
+    pub marks: Vec<bool>,
+}
+
+impl Schedule {
+    pub fn new(db: &DBStruct) -> Self {
+        let mut visits = db.visit.iter().filter(|v| !v.hidden).collect::<Vec<_>>();
+        visits.sort_by_key(|v| v.order);
+        let mut forms = db.form.iter().filter(|f| !f.hidden).collect::<Vec<_>>();
+        forms.sort_by_key(|f| f.order);
+        let binding = db
+            .binding
+            .iter()
+            .flat_map(|b| b.children.iter().map(|v| (b.parent, *v)))
+            .collect::<HashSet<(usize, usize)>>();
+        let rows = forms
+            .into_iter()
+            .map(|f| ScheduleRow {
+                form: f.name.clone(),
+                marks: visits
+                    .iter()
+                    .map(|v| binding.contains(&(f.id, v.id)))
+                    .collect(),
+            })
+            .collect();
+        Schedule {
+            visits: visits.into_iter().map(|v| v.name.clone()).collect(),
+            rows,
+        }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut lines = Vec::with_capacity(self.rows.len() + 1);
+        lines.push(csv_line(
+            std::iter::once("").chain(self.visits.iter().map(String::as_str)),
+        ));
+        for row in self.rows.iter() {
+            lines.push(csv_line(
+                std::iter::once(row.form.as_str()).chain(row.marks.iter().map(|m| mark(*m))),
+            ));
+        }
+        lines.join("\r\n")
+    }
+
+    /// html table only, used to embed the schedule into other pages
+    pub fn to_html_table(&self) -> String {
+        let mut html = String::from("<table class=\"schedule\">\n<thead>\n<tr><th></th>");
+        for visit in self.visits.iter() {
+            html.push_str(&format!("<th>{}</th>", escape_html(visit)));
+        }
+        html.push_str("</tr>\n</thead>\n<tbody>\n");
+        for row in self.rows.iter() {
+            html.push_str(&format!("<tr><th>{}</th>", escape_html(&row.form)));
+            for m in row.marks.iter() {
+                html.push_str(&format!("<td>{}</td>", mark(*m)));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</tbody>\n</table>");
+        html
+    }
+
+    pub fn to_html(&self) -> String {
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+
+<head>
+    <meta charset="UTF-8">
+    <style>
+        table {{ border-collapse: collapse; }}
+        th, td {{ border: 1px solid black; padding: 2px 4px; }}
+        td {{ text-align: center; }}
+        tbody th {{ text-align: left; font-weight: normal; }}
+    </style>
+</head>
+
+<body>
+{}
+</body>
+
+</html>
+"#,
+            self.to_html_table()
+        )
+    }
+
+    pub fn write_csv<P: AsRef<Path>>(&self, p: P) -> anyhow::Result<()> {
+        fs::write(p, self.to_csv())?;
+        Ok(())
+    }
+
+    pub fn write_html<P: AsRef<Path>>(&self, p: P) -> anyhow::Result<()> {
+        fs::write(p, self.to_html())?;
+        Ok(())
+    }
+
+    pub fn write_xlsx<P: AsRef<Path>>(&self, p: P) -> anyhow::Result<()> {
+        let mut workbook = Workbook::new();
+        let header = Format::new().set_bold().set_align(FormatAlign::Center);
+        let center = Format::new().set_align(FormatAlign::Center);
+        let sheet = workbook.add_worksheet();
+        sheet.set_name("Schedule")?;
+        for (col, visit) in self.visits.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16 + 1, visit, &header)?;
+        }
+        for (index, row) in self.rows.iter().enumerate() {
+            let r = index as u32 + 1;
+            sheet.write_string(r, 0, &row.form)?;
+            for (col, m) in row.marks.iter().enumerate() {
+                if *m {
+                    sheet.write_string_with_format(r, col as u16 + 1, MARK, &center)?;
+                }
+            }
+        }
+        sheet.set_column_width(0, 40)?;
+        sheet.set_freeze_panes(1, 1)?;
+        workbook.save(p)?;
+        Ok(())
+    }
+}
+
+fn mark(m: bool) -> &'static str {
+    if m {
+        MARK
+    } else {
+        ""
+    }
+}
+
+pub(crate) fn csv_line<'a>(cells: impl Iterator<Item = &'a str>) -> String {
+    cells
+        .map(|c| {
+            if c.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", c.replace('"', "\"\""))
+            } else {
+                c.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edc::db::tests::db;
+
+    #[test]
+    fn schedule_test() {
+        let schedule = Schedule::new(&db());
+        assert_eq!(schedule.visits, vec!["V1", "V2"]);
+        assert_eq!(schedule.rows[0].form, "Demographics");
+        assert_eq!(schedule.rows[0].marks, vec![true, false]);
+        assert_eq!(schedule.rows[1].form, "Vital Signs");
+        assert_eq!(schedule.rows[1].marks, vec![true, true]);
+    }
+
+    #[test]
+    fn schedule_hidden_test() {
+        let mut db = db();
+        db.form[1].hidden = true;
+        db.visit[1].hidden = true;
+        let schedule = Schedule::new(&db);
+        assert_eq!(schedule.visits, vec!["V1"]);
+        assert_eq!(schedule.rows.len(), 1);
+        assert_eq!(schedule.rows[0].form, "Vital Signs");
+        assert_eq!(schedule.rows[0].marks, vec![true]);
+    }
+
+    #[test]
+    fn schedule_escape_test() {
+        let mut db = db();
+        db.form[0].name = "AE, SAE".into();
+        db.form[1].name = "<DM>".into();
+        let schedule = Schedule::new(&db);
+        assert_eq!(schedule.to_csv(), ",V1,V2\r\n<DM>,X,\r\n\"AE, SAE\",X,X");
+        assert!(schedule.to_html_table().contains("<th>&lt;DM&gt;</th>"));
+    }
+}
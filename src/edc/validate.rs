@@ -0,0 +1,207 @@
+use crate::edc::db::DBStruct;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(message: String) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity.eq(&Severity::Error)
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.severity, self.message)
+    }
+}
+
+impl DBStruct {
+    /// check ids, bindings and pages, pass page count of source pdf to check form pages against it,
+    /// pages of hidden forms are not checked
+    pub fn validate(&self, source_pages: Option<usize>) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+
+        let mut visit_ids = HashSet::with_capacity(self.visit.len());
+        for visit in self.visit.iter() {
+            if !visit_ids.insert(visit.id) {
+                diagnostics.push(Diagnostic::error(format!(
+                    "visit id {} is used by more than one visit",
+                    visit.id
+                )));
+            }
+        }
+        let mut form_ids = HashSet::with_capacity(self.form.len());
+        let mut form_names: HashMap<&str, usize> = HashMap::with_capacity(self.form.len());
+        for form in self.form.iter() {
+            if !form_ids.insert(form.id) {
+                diagnostics.push(Diagnostic::error(format!(
+                    "form id {} is used by more than one form",
+                    form.id
+                )));
+            }
+            *form_names.entry(form.name.as_str()).or_default() += 1;
+            // hidden forms are not built, so their pages do not matter
+            if form.hidden {
+                continue;
+            }
+            if form.page.eq(&0) {
+                diagnostics.push(Diagnostic::error(format!(
+                    "form {} ({}) has no page in ecrf",
+                    form.id, form.name
+                )));
+            } else if let Some(pages) = source_pages {
+                if form.page.gt(&pages) {
+                    diagnostics.push(Diagnostic::error(format!(
+                        "page {} of form {} ({}) exceeds {} pages of source pdf",
+                        form.page, form.id, form.name, pages
+                    )));
+                }
+            }
+        }
+        let mut duplicated_names = form_names
+            .into_iter()
+            .filter(|(_, count)| count.gt(&1))
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        duplicated_names.sort();
+        for name in duplicated_names {
+            diagnostics.push(Diagnostic::warning(format!(
+                "form name {} is used by more than one form",
+                name
+            )));
+        }
+
+        let mut bound_forms = HashSet::with_capacity(self.binding.len());
+        let mut bound_visits = HashSet::with_capacity(self.visit.len());
+        for binding in self.binding.iter() {
+            if !form_ids.contains(&binding.parent) {
+                diagnostics.push(Diagnostic::error(format!(
+                    "binding refers to form id {} which does not exist",
+                    binding.parent
+                )));
+            }
+            if !bound_forms.insert(binding.parent) {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "form {} has more than one binding, visits of the bindings are merged",
+                    binding.parent
+                )));
+            }
+            let mut children = HashSet::with_capacity(binding.children.len());
+            for child in binding.children.iter() {
+                if !visit_ids.contains(child) {
+                    diagnostics.push(Diagnostic::error(format!(
+                        "binding of form {} refers to visit id {} which does not exist",
+                        binding.parent, child
+                    )));
+                }
+                if !children.insert(*child) {
+                    diagnostics.push(Diagnostic::warning(format!(
+                        "form {} is bound to visit {} more than once",
+                        binding.parent, child
+                    )));
+                }
+                bound_visits.insert(*child);
+            }
+        }
+        for form in self.form.iter() {
+            let has_visit = self
+                .binding
+                .iter()
+                .any(|b| b.parent.eq(&form.id) && !b.children.is_empty());
+            if !has_visit {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "form {} ({}) is not bound to any visit and will be missing from toc",
+                    form.id, form.name
+                )));
+            }
+        }
+        for visit in self.visit.iter() {
+            if !bound_visits.contains(&visit.id) {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "visit {} ({}) has no form and will be missing from toc",
+                    visit.id, visit.name
+                )));
+            }
+        }
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::edc::db::tests::{bind, db, form, visit};
+
+    #[test]
+    fn validate_test() {
+        let db = db();
+        assert!(db.validate(Some(3)).is_empty());
+
+        let diagnostics = db.validate(Some(2));
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].is_error());
+    }
+
+    #[test]
+    fn validate_page_test() {
+        let mut db = db();
+        db.form[1].page = 0;
+        let diagnostics = db.validate(None);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].is_error());
+
+        db.form[1].hidden = true;
+        assert!(db.validate(None).is_empty());
+    }
+
+    #[test]
+    fn validate_binding_test() {
+        let mut db = db();
+        db.form.push(form(1, "Adverse Events", 2));
+        db.binding.push(bind(9, &[5]));
+        let diagnostics = db.validate(None);
+        let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+        // duplicated form id, unknown form and unknown visit
+        assert_eq!(errors, 3);
+        assert_eq!(diagnostics.len(), errors);
+    }
+
+    #[test]
+    fn validate_warning_test() {
+        let mut db = db();
+        db.form.push(form(2, "Demographics", 2));
+        db.binding.push(bind(2, &[0]));
+        db.visit.push(visit(2, "V3"));
+        let diagnostics = db.validate(None);
+        // duplicated form name, visit without forms
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().all(|d| !d.is_error()));
+    }
+}
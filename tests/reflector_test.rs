@@ -1,5 +1,5 @@
 use reflector::{
-    acrf::builder::{ACrfBuilder, BuildParam},
+    acrf::builder::{ACrfBuilder, BuildOptions, BuildParam},
     edc::db::DBStruct,
 };
 use std::path::Path;
@@ -17,6 +17,7 @@ fn acrf_builder_test() -> anyhow::Result<()> {
         source,
         destination,
         bookmark_bin,
+        options: BuildOptions::default(),
     })?;
     Ok(())
 }